@@ -1,47 +1,333 @@
+#[cfg(any(feature = "ureq", feature = "hyper"))]
 use std::borrow::Cow;
+use std::time::Duration;
+#[cfg(any(feature = "ureq", feature = "hyper"))]
+use std::time::Instant;
+#[cfg(any(feature = "ureq", feature = "hyper"))]
+use url::Url;
 
 #[cfg(feature = "ureq")]
-use ureq::Agent;
+use ureq::AgentBuilder;
 
+#[cfg(any(feature = "ureq", feature = "hyper"))]
 use crate::error::WebPushError;
+#[cfg(any(feature = "ureq", feature = "hyper"))]
 use crate::message::WebPushMessage;
+#[cfg(feature = "hyper")]
+use crate::receipt::Receipt;
+#[cfg(any(feature = "ureq", feature = "hyper"))]
+use crate::receipt::ReceiptSubscription;
+use crate::retry::RetryPolicy;
 
 #[cfg(feature = "hyper")]
 use futures::stream::StreamExt;
 #[cfg(feature = "hyper")]
 use hyper::{
     client::HttpConnector,
-    header::{CONTENT_LENGTH, RETRY_AFTER},
-    Request,
+    header::{CONTENT_LENGTH, LOCATION, RETRY_AFTER, USER_AGENT},
+    http::request::Builder as RequestBuilder,
+    Body, Request, Response,
 };
 #[cfg(feature = "hyper")]
 use hyper_tls::HttpsConnector;
+#[cfg(feature = "hyper")]
+use std::future::Future;
+
+/// The response headers kept in a [SendOutcome](struct.SendOutcome.html).
+#[cfg(any(feature = "ureq", feature = "hyper"))]
+const HEADERS_OF_INTEREST: [&str; 4] = ["Location", "TTL", "Link", "Date"];
+
+/// What the push service answered to an accepted message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SendOutcome {
+    /// The status code, usually `201 Created`.
+    pub status: u16,
+    /// The push message resource from the `Location` header, as an absolute
+    /// URI. Used to cancel the message.
+    pub message_uri: Option<String>,
+    /// The TTL the push service applied, which can be lower than the one
+    /// requested. The requested TTL if the service did not say.
+    pub ttl: u32,
+    /// The `Location`, `TTL`, `Link` and `Date` headers of the response, if
+    /// sent.
+    pub headers: Vec<(&'static str, String)>,
+    /// The time from sending the request to receiving the response, of the
+    /// last attempt.
+    pub latency: Duration,
+    /// How many times the message was sent, more than once if it was retried
+    /// with a [RetryPolicy](struct.RetryPolicy.html).
+    pub attempts: u32,
+}
+
+impl SendOutcome {
+    #[cfg(any(feature = "ureq", feature = "hyper"))]
+    fn from_response<'r>(
+        message: &WebPushMessage,
+        status: u16,
+        header: impl Fn(&str) -> Option<&'r str>,
+        latency: Duration,
+    ) -> SendOutcome {
+        let headers: Vec<(&'static str, String)> = HEADERS_OF_INTEREST
+            .iter()
+            .filter_map(|name| header(name).map(|value| (*name, value.to_string())))
+            .collect();
+
+        let message_uri = resolve_location(&message.endpoint, header("Location"));
+
+        let ttl = header("TTL")
+            .and_then(|ttl| ttl.trim().parse().ok())
+            .unwrap_or(message.ttl);
+
+        SendOutcome {
+            status,
+            message_uri,
+            ttl,
+            headers,
+            latency,
+            attempts: 1,
+        }
+    }
+
+    /// The value of a kept header.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// What happened to a message when cancelling it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cancellation {
+    /// The message was withdrawn before it was delivered.
+    Cancelled,
+    /// The message was already delivered or it expired, the push service
+    /// answered `404 Not Found` or `410 Gone`.
+    DeliveredOrExpired,
+}
+
+/// The absolute URI of a `Location` header, which may be relative to the URI
+/// of the request.
+#[cfg(any(feature = "ureq", feature = "hyper"))]
+fn resolve_location(request_uri: &str, location: Option<&str>) -> Option<String> {
+    let location = location?;
+
+    Url::parse(request_uri)
+        .and_then(|request_uri| request_uri.join(location))
+        .map(String::from)
+        .ok()
+}
+
+#[cfg(any(feature = "ureq", feature = "hyper"))]
+fn receipt_subscription(
+    receipt_subscribe_uri: &str,
+    location: Option<&str>,
+) -> Result<ReceiptSubscription, WebPushError> {
+    resolve_location(receipt_subscribe_uri, location)
+        .map(ReceiptSubscription::new)
+        .ok_or(WebPushError::InvalidResponse)
+}
+
+/// Settings for creating a [BlockingWebPushClient](type.BlockingWebPushClient.html)
+/// or a [TokioWebPushClient](type.TokioWebPushClient.html). Without them the
+/// clients never time out.
+///
+/// ```
+/// # use web_push::*;
+/// # use std::time::Duration;
+/// let mut builder = WebPushClientBuilder::new();
+/// builder.set_connect_timeout(Duration::from_secs(5));
+/// builder.set_timeout(Duration::from_secs(30));
+/// builder.set_user_agent("my-app/1.0");
+///
+/// let client = builder.build_blocking();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct WebPushClientBuilder {
+    connect_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    timeout: Option<Duration>,
+    pool_max_idle_per_host: Option<usize>,
+    pool_idle_timeout: Option<Duration>,
+    user_agent: Option<String>,
+    retry_policy: Option<RetryPolicy>,
+}
+
+impl WebPushClientBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How long to wait for a connection to the push service.
+    pub fn set_connect_timeout(&mut self, timeout: Duration) {
+        self.connect_timeout = Some(timeout);
+    }
+
+    /// How long to wait for data from the push service. The tokio client
+    /// waits this long for the response headers.
+    pub fn set_read_timeout(&mut self, timeout: Duration) {
+        self.read_timeout = Some(timeout);
+    }
+
+    /// How long a request may take overall, from connecting to reading the
+    /// response. Can be overridden per message with
+    /// [set_timeout](struct.WebPushMessageBuilder.html#method.set_timeout).
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
+    /// How many idle connections to keep open to each push service.
+    pub fn set_pool_max_idle_per_host(&mut self, max_idle: usize) {
+        self.pool_max_idle_per_host = Some(max_idle);
+    }
+
+    /// How long to keep idle connections open. Only applies to the tokio
+    /// client: ureq has no idle timeout, so
+    /// [build_blocking](#method.build_blocking) ignores it and keeps idle
+    /// connections until the pool is full.
+    pub fn set_pool_idle_timeout(&mut self, timeout: Duration) {
+        self.pool_idle_timeout = Some(timeout);
+    }
+
+    /// The `User-Agent` header sent with every request.
+    pub fn set_user_agent<S>(&mut self, user_agent: S)
+    where
+        S: Into<String>,
+    {
+        self.user_agent = Some(user_agent.into());
+    }
+
+    /// Retries sending messages that failed. Without a policy every message
+    /// is sent once.
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = Some(retry_policy);
+    }
+
+    #[cfg(feature = "ureq")]
+    pub fn build_blocking(&self) -> BlockingWebPushClient {
+        let mut agent = AgentBuilder::new();
+
+        if let Some(timeout) = self.connect_timeout {
+            agent = agent.timeout_connect(timeout);
+        }
+
+        if let Some(timeout) = self.read_timeout {
+            agent = agent.timeout_read(timeout);
+        }
+
+        if let Some(timeout) = self.timeout {
+            agent = agent.timeout(timeout);
+        }
+
+        // ureq has no idle timeout, `pool_idle_timeout` is only for tokio.
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            agent = agent.max_idle_connections_per_host(max_idle);
+        }
+
+        if let Some(user_agent) = &self.user_agent {
+            agent = agent.user_agent(user_agent);
+        }
+
+        WebPushClient {
+            client: agent.build(),
+            settings: self.clone(),
+        }
+    }
+
+    #[cfg(feature = "hyper")]
+    pub fn build_tokio(&self) -> TokioWebPushClient {
+        let mut http = HttpConnector::new();
+        http.enforce_http(false);
+        http.set_connect_timeout(self.connect_timeout);
+
+        let mut client = hyper::Client::builder();
+        client.pool_idle_timeout(self.pool_idle_timeout);
+
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            client.pool_max_idle_per_host(max_idle);
+        }
+
+        WebPushClient {
+            client: client.build(HttpsConnector::new_with_connector(http)),
+            settings: self.clone(),
+        }
+    }
+}
 
 /// An client for sending the notification payload.
 pub struct WebPushClient<Client> {
+    #[cfg_attr(not(any(feature = "ureq", feature = "hyper")), allow(dead_code))]
     client: Client,
+    #[cfg(any(feature = "ureq", feature = "hyper"))]
+    settings: WebPushClientBuilder,
 }
 
 impl<Client> WebPushClient<Client> {
+    /// The wait before sending again, if the policy allows another attempt.
+    #[cfg(any(feature = "ureq", feature = "hyper"))]
+    fn retry_delay(
+        &self,
+        attempts: u32,
+        error: &WebPushError,
+        started: Instant,
+    ) -> Option<Duration> {
+        let delay =
+            self.settings
+                .retry_policy
+                .as_ref()?
+                .delay(attempts, error, started.elapsed())?;
+
+        debug!(
+            "Attempt {} failed with {}, retrying in {:?}",
+            attempts, error, delay
+        );
+
+        Some(delay)
+    }
+
+    #[cfg(any(feature = "ureq", feature = "hyper"))]
     fn headers(message: &WebPushMessage) -> impl IntoIterator<Item = (&'static str, Cow<'_, str>)> {
-        let ttl_header: (&'static str, Cow<'_, str>) = ("TTL", message.ttl.to_string().into());
-        if let Some(payload) = &message.payload {
-            let mut headers = vec![
-                ttl_header,
-                ("Content-Encoding", payload.content_encoding.into()),
-                ("Content-Length", payload.content.len().to_string().into()),
-                ("Content-Type", "application/octet-stream".into()),
-            ];
-            headers.extend(
-                payload
-                    .crypto_headers
-                    .iter()
-                    .map(|(k, v)| (*k, Cow::Borrowed(v.as_str()))),
-            );
-            headers
-        } else {
-            vec![ttl_header]
+        let mut headers: Vec<(&'static str, Cow<'_, str>)> =
+            vec![("TTL", message.ttl.to_string().into())];
+
+        if let Some(urgency) = message.urgency {
+            headers.push(("Urgency", urgency.as_str().into()));
+        }
+
+        if let Some(topic) = &message.topic {
+            headers.push(("Topic", topic.as_str().into()));
+        }
+
+        if let Some(receipt_subscription) = &message.receipt_subscription {
+            headers.push(("Push-Receipt", receipt_subscription.as_str().into()));
+            headers.push(("Prefer", "respond-async".into()));
         }
+
+        let crypto_headers = match &message.payload {
+            Some(payload) => {
+                headers.extend(vec![
+                    ("Content-Encoding", payload.content_encoding.into()),
+                    ("Content-Length", payload.content.len().to_string().into()),
+                    ("Content-Type", "application/octet-stream".into()),
+                ]);
+                payload.crypto_headers.as_slice()
+            }
+            None => &[],
+        };
+
+        // The VAPID key of the `WebPush` scheme goes to the same `Crypto-Key`
+        // header as the key of the aesgcm encryption.
+        for (name, value) in crypto_headers.iter().chain(message.auth_headers.iter()) {
+            match headers.iter_mut().find(|(existing, _)| existing == name) {
+                Some((_, existing)) if *name == "Crypto-Key" => {
+                    *existing = format!("{}; {}", existing, value).into();
+                }
+                _ => headers.push((*name, Cow::Borrowed(value.as_str()))),
+            }
+        }
+
+        headers
     }
 }
 
@@ -51,28 +337,92 @@ pub type BlockingWebPushClient = WebPushClient<ureq::Agent>;
 
 #[cfg(feature = "ureq")]
 impl BlockingWebPushClient {
+    /// A client that never times out. Use
+    /// [WebPushClientBuilder](struct.WebPushClientBuilder.html) to set
+    /// timeouts.
     pub fn new() -> Self {
-        Self {
-            client: Agent::new(),
+        WebPushClientBuilder::new().build_blocking()
+    }
+
+    /// Sends a notification. Blocking. Retried as set in the
+    /// [RetryPolicy](struct.RetryPolicy.html), in which case the error of the
+    /// last attempt is returned.
+    pub fn send(&self, message: WebPushMessage) -> Result<SendOutcome, WebPushError> {
+        let started = Instant::now();
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            match self.send_once(&message) {
+                Ok(outcome) => {
+                    return Ok(SendOutcome {
+                        attempts,
+                        ..outcome
+                    })
+                }
+                Err(error) => match self.retry_delay(attempts, &error, started) {
+                    Some(delay) => std::thread::sleep(delay),
+                    None => return Err(error),
+                },
+            }
         }
     }
 
-    /// Sends a notification. Blocking. Never times out.
-    pub fn send(&self, message: WebPushMessage) -> Result<(), WebPushError> {
+    fn send_once(&self, message: &WebPushMessage) -> Result<SendOutcome, WebPushError> {
         let mut request = self.client.post(&message.endpoint);
-        for (header, value) in Self::headers(&message) {
+        for (header, value) in Self::headers(message) {
             request = request.set(header, &value);
         }
 
-        let body = if let Some(payload) = message.payload {
-            payload.content
-        } else {
-            vec![]
+        if let Some(timeout) = message.timeout {
+            request = request.timeout(timeout);
+        }
+
+        let body = match &message.payload {
+            Some(payload) => payload.content.as_slice(),
+            None => &[],
         };
-        let response = request.send_bytes(&body)?;
+
+        let started = Instant::now();
+        let response = request.send_bytes(body)?;
+        let latency = started.elapsed();
 
         trace!("Response: {:?}", response);
-        Ok(())
+
+        Ok(SendOutcome::from_response(
+            message,
+            response.status(),
+            |name| response.header(name),
+            latency,
+        ))
+    }
+
+    /// Creates a receipt subscription at the receipt subscribe resource of
+    /// the push service, see
+    /// [ReceiptSubscription::subscribe_uri](struct.ReceiptSubscription.html#method.subscribe_uri).
+    pub fn subscribe_receipts(
+        &self,
+        receipt_subscribe_uri: &str,
+    ) -> Result<ReceiptSubscription, WebPushError> {
+        let response = self.client.post(receipt_subscribe_uri).send_bytes(&[])?;
+
+        receipt_subscription(receipt_subscribe_uri, response.header("Location"))
+    }
+
+    /// Withdraws a message that was not delivered yet, by deleting the push
+    /// message resource from the
+    /// [message_uri](struct.SendOutcome.html#structfield.message_uri) of its
+    /// outcome. See [RFC 8030 section
+    /// 6.2](https://tools.ietf.org/html/rfc8030#section-6.2).
+    pub fn cancel(&self, message_uri: &str) -> Result<Cancellation, WebPushError> {
+        match self.client.delete(message_uri).call() {
+            Ok(_) => Ok(Cancellation::Cancelled),
+            Err(ureq::Error::Status(404, _)) | Err(ureq::Error::Status(410, _)) => {
+                Ok(Cancellation::DeliveredOrExpired)
+            }
+            Err(error) => Err(error.into()),
+        }
     }
 }
 
@@ -89,70 +439,299 @@ pub type TokioWebPushClient = WebPushClient<hyper::Client<HttpsConnector<HttpCon
 
 #[cfg(feature = "hyper")]
 impl TokioWebPushClient {
+    /// A client that never times out. Use
+    /// [WebPushClientBuilder](struct.WebPushClientBuilder.html) to set
+    /// timeouts.
     pub fn new() -> Self {
-        Self {
-            client: hyper::Client::builder().build(HttpsConnector::new()),
+        WebPushClientBuilder::new().build_tokio()
+    }
+
+    /// Sends a notification. Asynchronous. Retried as set in the
+    /// [RetryPolicy](struct.RetryPolicy.html), in which case the error of the
+    /// last attempt is returned.
+    pub async fn send(&self, message: WebPushMessage) -> Result<SendOutcome, WebPushError> {
+        let timeout = message.timeout.or(self.settings.timeout);
+        let started = Instant::now();
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            match with_timeout(timeout, self.send_message(&message)).await {
+                Ok(outcome) => {
+                    return Ok(SendOutcome {
+                        attempts,
+                        ..outcome
+                    })
+                }
+                Err(error) => match self.retry_delay(attempts, &error, started) {
+                    Some(delay) => tokio::time::sleep(delay).await,
+                    None => return Err(error),
+                },
+            }
         }
     }
 
-    /// Sends a notification. Asynchronous. Never times out.
-    pub async fn send(&self, message: WebPushMessage) -> Result<(), WebPushError> {
-        let mut request = Request::builder().method("POST").uri(&message.endpoint);
-        for (header, value) in Self::headers(&message) {
+    async fn send_message(&self, message: &WebPushMessage) -> Result<SendOutcome, WebPushError> {
+        let mut request = self.request_builder("POST", &message.endpoint);
+        for (header, value) in Self::headers(message) {
             request = request.header(header, value.as_ref());
         }
 
-        let body = if let Some(payload) = message.payload {
-            payload.content
-        } else {
-            vec![]
+        let body = match &message.payload {
+            Some(payload) => payload.content.clone(),
+            None => vec![],
         };
         let request = request.body(body.into()).unwrap();
         trace!("request headers {:?}", request.headers());
 
-        let response = self.client.request(request).await?;
+        let started = Instant::now();
+        let response = self.call(request).await?;
+        let latency = started.elapsed();
 
         trace!("response status {}", response.status());
 
         if response.status().is_success() {
-            Ok(())
+            Ok(SendOutcome::from_response(
+                message,
+                response.status().as_u16(),
+                |name| response.headers().get(name).and_then(|v| v.to_str().ok()),
+                latency,
+            ))
         } else {
-            let status = response.status().as_u16();
-            let retry_after = response
+            Err(error_from_response(response).await)
+        }
+    }
+
+    /// Creates a receipt subscription at the receipt subscribe resource of
+    /// the push service, see
+    /// [ReceiptSubscription::subscribe_uri](struct.ReceiptSubscription.html#method.subscribe_uri).
+    pub async fn subscribe_receipts(
+        &self,
+        receipt_subscribe_uri: &str,
+    ) -> Result<ReceiptSubscription, WebPushError> {
+        with_timeout(self.settings.timeout, async {
+            let request = self
+                .request_builder("POST", receipt_subscribe_uri)
+                .body(Body::empty())?;
+
+            let response = self.call(request).await?;
+
+            if !response.status().is_success() {
+                return Err(error_from_response(response).await);
+            }
+
+            let location = response
                 .headers()
-                .get(RETRY_AFTER)
-                .and_then(|s| s.to_str().ok())
-                .and_then(|ra| crate::error::retry_after_from_str(ra));
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok());
+            receipt_subscription(receipt_subscribe_uri, location)
+        })
+        .await
+    }
+
+    /// Withdraws a message that was not delivered yet, by deleting the push
+    /// message resource from the
+    /// [message_uri](struct.SendOutcome.html#structfield.message_uri) of its
+    /// outcome. See [RFC 8030 section
+    /// 6.2](https://tools.ietf.org/html/rfc8030#section-6.2).
+    pub async fn cancel(&self, message_uri: &str) -> Result<Cancellation, WebPushError> {
+        with_timeout(self.settings.timeout, async {
+            let request = self
+                .request_builder("DELETE", message_uri)
+                .body(Body::empty())?;
 
-            let content_length: usize = response
+            let response = self.call(request).await?;
+
+            match response.status().as_u16() {
+                404 | 410 => Ok(Cancellation::DeliveredOrExpired),
+                _ if response.status().is_success() => Ok(Cancellation::Cancelled),
+                _ => Err(error_from_response(response).await),
+            }
+        })
+        .await
+    }
+
+    /// Polls the receipt subscription for the receipts of the messages sent
+    /// with it. This is a non-standard fallback: RFC 8030 push services
+    /// deliver receipts with HTTP/2 server push, which the client does not
+    /// support, so it only works with push services or proxies that answer
+    /// `GET` on the receipt subscription, see
+    /// [ReceiptPoller](struct.ReceiptPoller.html).
+    ///
+    /// ```no_run
+    /// # use web_push::*;
+    /// # async fn run(client: TokioWebPushClient, message: WebPushMessage) -> Result<(), WebPushError> {
+    /// let receipt_subscription = client
+    ///     .subscribe_receipts("https://push.example.net/receipts/subscribe")
+    ///     .await?;
+    ///
+    /// // The message is built with `set_receipt_subscription(&receipt_subscription)`
+    /// let mut tracker = ReceiptTracker::new();
+    /// let outcome = client.send(message).await?;
+    /// tracker.insert(&outcome, "security-alert-42");
+    ///
+    /// let mut receipts = client.poll_receipts(&receipt_subscription);
+    ///
+    /// while tracker.pending() > 0 {
+    ///     let receipt = receipts.next().await?;
+    ///
+    ///     if let Some(alert) = tracker.resolve(&receipt) {
+    ///         println!("{} was delivered", alert);
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn poll_receipts(&self, receipt_subscription: &ReceiptSubscription) -> ReceiptPoller<'_> {
+        ReceiptPoller {
+            client: self,
+            uri: receipt_subscription.uri.clone(),
+            empty_polls: 0,
+        }
+    }
+
+    fn request_builder(&self, method: &str, uri: &str) -> RequestBuilder {
+        let request = Request::builder().method(method).uri(uri);
+
+        match &self.settings.user_agent {
+            Some(user_agent) => request.header(USER_AGENT, user_agent.as_str()),
+            None => request,
+        }
+    }
+
+    /// Sends the request, waiting for the response headers at most the read
+    /// timeout.
+    async fn call(&self, request: Request<Body>) -> Result<Response<Body>, WebPushError> {
+        with_timeout(self.settings.read_timeout, async {
+            Ok(self.client.request(request).await?)
+        })
+        .await
+    }
+}
+
+#[cfg(feature = "hyper")]
+async fn with_timeout<T, F>(timeout: Option<Duration>, future: F) -> Result<T, WebPushError>
+where
+    F: Future<Output = Result<T, WebPushError>>,
+{
+    match timeout {
+        Some(timeout) => match tokio::time::timeout(timeout, future).await {
+            Ok(result) => result,
+            Err(_) => Err(WebPushError::Timeout),
+        },
+        None => future.await,
+    }
+}
+
+/// Polls a receipt subscription for receipts, created with
+/// [TokioWebPushClient::poll_receipts](type.TokioWebPushClient.html#method.poll_receipts).
+///
+/// This is not the receipt delivery of RFC 8030, which pushes receipts to the
+/// application server with HTTP/2 server push, and does not work with push
+/// services that only implement the RFC. It is a fallback for push services
+/// or proxies in front of them that answer a `GET` of the receipt
+/// subscription with a `2xx` status and the push message resource of a
+/// delivered message in the `Location` header, and may hold the request open
+/// until there is one. Answers without a `Location` are taken as no receipt
+/// yet, and the receipt subscription is polled again after the backoff of the
+/// [RetryPolicy](struct.RetryPolicy.html) of the client.
+///
+/// Each poll fails with `WebPushError::Timeout` after the timeout set with
+/// [WebPushClientBuilder::set_timeout](struct.WebPushClientBuilder.html#method.set_timeout),
+/// after which `next` can be called again.
+#[cfg(feature = "hyper")]
+pub struct ReceiptPoller<'c> {
+    client: &'c TokioWebPushClient,
+    uri: String,
+    empty_polls: u32,
+}
+
+#[cfg(feature = "hyper")]
+impl ReceiptPoller<'_> {
+    /// Waits for the next receipt.
+    pub async fn next(&mut self) -> Result<Receipt, WebPushError> {
+        loop {
+            let request = self
+                .client
+                .request_builder("GET", &self.uri)
+                .body(Body::empty())?;
+
+            // The poll may be held open until there is a receipt.
+            let response = with_timeout(self.client.settings.timeout, async {
+                Ok(self.client.client.request(request).await?)
+            })
+            .await?;
+
+            if !response.status().is_success() {
+                return Err(error_from_response(response).await);
+            }
+
+            let location = response
                 .headers()
-                .get(CONTENT_LENGTH)
-                .and_then(|s| s.to_str().ok())
-                .and_then(|s| s.parse().ok())
-                .unwrap_or(0);
+                .get(LOCATION)
+                .and_then(|v| v.to_str().ok());
 
-            let mut body: Vec<u8> = Vec::with_capacity(content_length);
-            let mut chunks = response.into_body();
+            if let Some(message_uri) = resolve_location(&self.uri, location) {
+                self.empty_polls = 0;
 
-            while let Some(chunk) = chunks.next().await {
-                body.extend_from_slice(&chunk?);
+                return Ok(Receipt {
+                    message_uri,
+                    status: response.status().as_u16(),
+                });
             }
 
-            let read_body_as_error_info_json = || match String::from_utf8(body) {
-                Err(_) => Err(WebPushError::BadRequest(None)),
-                Ok(body_str) => match serde_json::from_str::<crate::error::ErrorInfo>(&body_str) {
-                    Ok(error_info) => Ok(error_info),
-                    Err(_) => Err(WebPushError::BadRequest(None)),
-                },
-            };
+            self.empty_polls += 1;
+            tokio::time::sleep(self.backoff()).await;
+        }
+    }
 
-            Err(WebPushError::from_error_response(
-                status,
-                retry_after,
-                read_body_as_error_info_json,
-            ))
+    /// The wait before polling again after empty answers, from the retry
+    /// policy of the client or the default one.
+    fn backoff(&self) -> Duration {
+        match &self.client.settings.retry_policy {
+            Some(policy) => policy.backoff(self.empty_polls),
+            None => RetryPolicy::new(1).backoff(self.empty_polls),
+        }
+    }
+}
+
+/// Reads the error of a response that was not successful.
+#[cfg(feature = "hyper")]
+async fn error_from_response(response: Response<Body>) -> WebPushError {
+    let status = response.status().as_u16();
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|s| s.to_str().ok())
+        .and_then(crate::error::retry_after_from_str);
+
+    let content_length: usize = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|s| s.to_str().ok())
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(0);
+
+    let mut body: Vec<u8> = Vec::with_capacity(content_length);
+    let mut chunks = response.into_body();
+
+    while let Some(chunk) = chunks.next().await {
+        match chunk {
+            Ok(chunk) => body.extend_from_slice(&chunk),
+            Err(error) => return error.into(),
         }
     }
+
+    let read_body_as_error_info_json = || match String::from_utf8(body) {
+        Err(_) => Err(WebPushError::BadRequest(None)),
+        Ok(body_str) => match serde_json::from_str::<crate::error::ErrorInfo>(&body_str) {
+            Ok(error_info) => Ok(error_info),
+            Err(_) => Err(WebPushError::BadRequest(None)),
+        },
+    };
+
+    WebPushError::from_error_response(status, retry_after, read_body_as_error_info_json)
 }
 
 #[cfg(feature = "hyper")]
@@ -161,3 +740,610 @@ impl Default for TokioWebPushClient {
         Self::new()
     }
 }
+
+#[cfg(all(test, any(feature = "ureq", feature = "hyper")))]
+mod tests {
+    use crate::http_ece::ContentEncoding;
+    use crate::message::{SubscriptionInfo, Urgency, WebPushMessage, WebPushMessageBuilder};
+    use crate::receipt::ReceiptSubscription;
+    #[cfg(feature = "hyper")]
+    use crate::receipt::ReceiptTracker;
+    use crate::test_server::{Request, Response, Stall, TestServer};
+    use crate::vapid::{VapidKey, VapidSigner};
+    use crate::{Cancellation, RetryPolicy, SendOutcome, WebPushClientBuilder, WebPushError};
+    use std::fs::File;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    const P256DH: &str =
+        "BLMbF9ffKBiWQLCKvTHb6LO8Nb6dcUh6TItC455vu2kElga6PQvUmaFyCdykxY2nOSSL3yKgfbmFLRTUaGv4yV8";
+    const AUTH: &str = "xS03Fi5ErfTNH_l9WHE9Ig";
+
+    /// A message to a test subscription at the endpoint, with the options
+    /// set by `options`.
+    fn message<F>(endpoint: &str, options: F) -> WebPushMessage
+    where
+        F: FnOnce(&mut WebPushMessageBuilder),
+    {
+        let subscription_info = SubscriptionInfo::new(endpoint, P256DH, AUTH);
+        let mut builder = WebPushMessageBuilder::new(&subscription_info).unwrap();
+        options(&mut builder);
+        builder.build().unwrap()
+    }
+
+    /// The blocking or the tokio client behind the same blocking interface,
+    /// the tokio client running on a runtime of its own.
+    enum Client {
+        #[cfg(feature = "ureq")]
+        Blocking(crate::BlockingWebPushClient),
+        #[cfg(feature = "hyper")]
+        Tokio(crate::TokioWebPushClient, tokio::runtime::Runtime),
+    }
+
+    impl Client {
+        fn send(&self, message: WebPushMessage) -> Result<SendOutcome, WebPushError> {
+            match self {
+                #[cfg(feature = "ureq")]
+                Client::Blocking(client) => client.send(message),
+                #[cfg(feature = "hyper")]
+                Client::Tokio(client, runtime) => runtime.block_on(client.send(message)),
+            }
+        }
+
+        fn subscribe_receipts(&self, uri: &str) -> Result<ReceiptSubscription, WebPushError> {
+            match self {
+                #[cfg(feature = "ureq")]
+                Client::Blocking(client) => client.subscribe_receipts(uri),
+                #[cfg(feature = "hyper")]
+                Client::Tokio(client, runtime) => runtime.block_on(client.subscribe_receipts(uri)),
+            }
+        }
+
+        fn cancel(&self, message_uri: &str) -> Result<Cancellation, WebPushError> {
+            match self {
+                #[cfg(feature = "ureq")]
+                Client::Blocking(client) => client.cancel(message_uri),
+                #[cfg(feature = "hyper")]
+                Client::Tokio(client, runtime) => runtime.block_on(client.cancel(message_uri)),
+            }
+        }
+    }
+
+    /// Builds the clients of a scenario.
+    #[derive(Debug, Clone, Copy)]
+    enum ClientKind {
+        #[cfg(feature = "ureq")]
+        Blocking,
+        #[cfg(feature = "hyper")]
+        Tokio,
+    }
+
+    impl ClientKind {
+        fn build(self, builder: &WebPushClientBuilder) -> Client {
+            match self {
+                #[cfg(feature = "ureq")]
+                ClientKind::Blocking => Client::Blocking(builder.build_blocking()),
+                #[cfg(feature = "hyper")]
+                ClientKind::Tokio => {
+                    let runtime = tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .unwrap();
+
+                    Client::Tokio(builder.build_tokio(), runtime)
+                }
+            }
+        }
+
+        fn client(self) -> Client {
+            self.build(&WebPushClientBuilder::new())
+        }
+    }
+
+    /// Runs the scenario with each of the enabled clients.
+    fn each_client<F>(scenario: F)
+    where
+        F: Fn(ClientKind),
+    {
+        #[cfg(feature = "ureq")]
+        scenario(ClientKind::Blocking);
+        #[cfg(feature = "hyper")]
+        scenario(ClientKind::Tokio);
+    }
+
+    const ENCODINGS: [Option<ContentEncoding>; 3] = [
+        None,
+        Some(ContentEncoding::AesGcm),
+        Some(ContentEncoding::Aes128Gcm),
+    ];
+
+    fn signed_message(endpoint: &str, encoding: Option<ContentEncoding>) -> WebPushMessage {
+        let key = VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap();
+        let signature = VapidSigner::new(key, "mailto:admin@example.com")
+            .sign_for_endpoint(endpoint)
+            .unwrap();
+
+        message(endpoint, |builder| {
+            builder.set_vapid_signature(signature);
+
+            if let Some(encoding) = encoding {
+                builder.set_payload(encoding, b"hello");
+            }
+        })
+    }
+
+    fn assert_vapid_headers(request: &Request, encoding: Option<ContentEncoding>) {
+        let authorization = request.header("Authorization").unwrap();
+        let crypto_key = request.header("Crypto-Key");
+
+        match encoding {
+            Some(ContentEncoding::AesGcm) => {
+                assert!(authorization.starts_with("WebPush "));
+
+                let crypto_key = crypto_key.unwrap();
+                assert!(crypto_key.starts_with("dh="));
+                assert!(crypto_key.contains("; p256ecdsa=BMo1HqKF6skMZYyk"));
+                assert_eq!("aesgcm", request.header("Content-Encoding").unwrap());
+            }
+            Some(ContentEncoding::Aes128Gcm) => {
+                assert!(authorization.starts_with("vapid t="));
+                assert_eq!(None, crypto_key);
+                assert_eq!("aes128gcm", request.header("Content-Encoding").unwrap());
+            }
+            None => {
+                assert!(authorization.starts_with("vapid t="));
+                assert!(authorization.contains(", k=BMo1HqKF6skMZYyk"));
+                assert_eq!(None, crypto_key);
+                assert_eq!(None, request.header("Content-Encoding"));
+                assert!(request.body.is_empty());
+            }
+        }
+
+        assert_eq!("POST", request.method);
+        assert_eq!("/push", request.path);
+        assert_eq!("2419200", request.header("TTL").unwrap());
+        assert_eq!(None, request.header("Urgency"));
+        assert_eq!(None, request.header("Topic"));
+    }
+
+    #[test]
+    fn test_vapid_headers() {
+        each_client(|kind| {
+            let server = TestServer::new();
+            let client = kind.client();
+
+            for encoding in ENCODINGS.iter() {
+                client
+                    .send(signed_message(&server.url("/push"), *encoding))
+                    .unwrap();
+            }
+
+            let requests = server.requests();
+            assert_eq!(3, requests.len());
+
+            for (request, encoding) in requests.iter().zip(ENCODINGS.iter()) {
+                assert_vapid_headers(request, *encoding);
+            }
+        });
+    }
+
+    #[test]
+    fn test_headers_without_vapid() {
+        let message = message("https://example.com/push", |_| {});
+
+        let headers: Vec<_> = super::WebPushClient::<()>::headers(&message)
+            .into_iter()
+            .map(|(name, value)| (name, value.into_owned()))
+            .collect();
+
+        assert_eq!(vec![("TTL", String::from("2419200"))], headers);
+    }
+
+    #[test]
+    fn test_urgency() {
+        each_client(|kind| {
+            let server = TestServer::new();
+            let client = kind.client();
+
+            for urgency in &[
+                Urgency::VeryLow,
+                Urgency::Low,
+                Urgency::Normal,
+                Urgency::High,
+            ] {
+                client
+                    .send(message(&server.url("/push"), |builder| {
+                        builder.set_urgency(*urgency)
+                    }))
+                    .unwrap();
+            }
+
+            let received: Vec<_> = server
+                .requests()
+                .iter()
+                .map(|request| request.header("Urgency").unwrap().to_string())
+                .collect();
+
+            assert_eq!(vec!["very-low", "low", "normal", "high"], received);
+        });
+    }
+
+    /// A message replacing an undelivered one with the same topic.
+    fn topic_message(endpoint: &str, topic: &str) -> WebPushMessage {
+        message(endpoint, |builder| {
+            builder.set_topic(topic).unwrap();
+            builder.set_payload(ContentEncoding::Aes128Gcm, b"3 unread messages");
+        })
+    }
+
+    #[test]
+    fn test_topic() {
+        each_client(|kind| {
+            let server = TestServer::new();
+
+            kind.client()
+                .send(topic_message(&server.url("/push"), "unread-count"))
+                .unwrap();
+
+            assert_eq!(Some("unread-count"), server.requests()[0].header("Topic"));
+        });
+    }
+
+    /// Lowers the TTL of messages with a payload and omits it for others.
+    fn push_service() -> TestServer {
+        TestServer::with_handler(|request| {
+            let response = Response::new(201)
+                .header("Location", "/m/AbC123")
+                .header("Date", "Sun, 18 Oct 2026 10:00:00 GMT");
+
+            match request.body.len() {
+                0 => response,
+                _ => response.header("TTL", "3600"),
+            }
+        })
+    }
+
+    #[test]
+    fn test_send_outcome() {
+        each_client(|kind| {
+            let server = push_service();
+            let client = kind.client();
+            let message_uri = server.url("/m/AbC123");
+
+            let outcomes: Vec<_> = ENCODINGS[..2]
+                .iter()
+                .rev()
+                .map(|encoding| {
+                    client
+                        .send(signed_message(&server.url("/push"), *encoding))
+                        .unwrap()
+                })
+                .collect();
+
+            for outcome in &outcomes {
+                assert_eq!(201, outcome.status);
+                assert_eq!(Some(message_uri.as_str()), outcome.message_uri.as_deref());
+                assert_eq!(Some("/m/AbC123"), outcome.header("location"));
+                assert_eq!(
+                    Some("Sun, 18 Oct 2026 10:00:00 GMT"),
+                    outcome.header("Date")
+                );
+                assert!(outcome.latency < Duration::from_secs(10));
+            }
+
+            // Lowered by the service with a payload, as requested without.
+            assert_eq!(3600, outcomes[0].ttl);
+            assert_eq!(Some("3600"), outcomes[0].header("TTL"));
+            assert_eq!(2_419_200, outcomes[1].ttl);
+            assert_eq!(None, outcomes[1].header("TTL"));
+        });
+    }
+
+    /// Accepts messages asking for a receipt and reports them delivered in
+    /// reverse order, when the receipt subscription is polled.
+    fn receipt_service() -> TestServer {
+        let sent = AtomicUsize::new(0);
+        let delivered = AtomicUsize::new(0);
+
+        TestServer::with_handler(move |request| {
+            match (request.method.as_str(), request.path.as_str()) {
+                ("POST", "/receipts/subscribe") => {
+                    Response::new(201).header("Location", "/receipts/r1")
+                }
+                ("POST", "/push")
+                    if request.header("Prefer") == Some("respond-async")
+                        && request
+                            .header("Push-Receipt")
+                            .is_some_and(|uri| uri.ends_with("/receipts/r1")) =>
+                {
+                    let id = sent.fetch_add(1, Ordering::SeqCst) + 1;
+                    Response::new(202).header("Location", &format!("/m/{}", id))
+                }
+                ("GET", "/receipts/r1") => match delivered.fetch_add(1, Ordering::SeqCst) {
+                    // A long poll that ended without a receipt.
+                    0 => Response::new(204),
+                    n => Response::new(204).header("Location", &format!("/m/{}", 3 - n)),
+                },
+                _ => Response::new(400),
+            }
+        })
+    }
+
+    fn receipt_message(
+        endpoint: &str,
+        receipt_subscription: &ReceiptSubscription,
+    ) -> WebPushMessage {
+        message(endpoint, |builder| {
+            builder.set_receipt_subscription(receipt_subscription)
+        })
+    }
+
+    #[test]
+    fn test_receipts() {
+        each_client(|kind| {
+            let server = receipt_service();
+            let client = kind.client();
+
+            let receipt_subscription = client
+                .subscribe_receipts(&server.url("/receipts/subscribe"))
+                .unwrap();
+            assert_eq!(server.url("/receipts/r1"), receipt_subscription.uri);
+
+            for id in 1..=2 {
+                let outcome = client
+                    .send(receipt_message(&server.url("/push"), &receipt_subscription))
+                    .unwrap();
+
+                assert_eq!(202, outcome.status);
+                assert_eq!(Some(server.url(&format!("/m/{}", id))), outcome.message_uri);
+            }
+
+            assert_eq!(
+                Err(WebPushError::BadRequest(None)),
+                client.subscribe_receipts(&server.url("/receipts/unknown"))
+            );
+        });
+    }
+
+    #[cfg(feature = "hyper")]
+    #[tokio::test]
+    async fn test_tokio_client_poll_receipts() {
+        let server = receipt_service();
+        let client = retrying_builder().build_tokio();
+        let receipt_subscription = ReceiptSubscription::new(server.url("/receipts/r1"));
+        let mut tracker = ReceiptTracker::new();
+
+        for alert in &["first alert", "second alert"] {
+            let outcome = client
+                .send(receipt_message(&server.url("/push"), &receipt_subscription))
+                .await
+                .unwrap();
+
+            assert!(tracker.insert(&outcome, *alert));
+        }
+
+        let mut receipts = client.poll_receipts(&receipt_subscription);
+        let mut delivered = Vec::new();
+
+        while tracker.pending() > 0 {
+            let receipt = receipts.next().await.unwrap();
+            assert_eq!(204, receipt.status);
+            delivered.push(tracker.resolve(&receipt).unwrap());
+        }
+
+        assert_eq!(vec!["second alert", "first alert"], delivered);
+
+        // The empty poll, and one for each receipt.
+        let polls = server
+            .requests()
+            .iter()
+            .filter(|request| request.method == "GET")
+            .count();
+        assert_eq!(3, polls);
+    }
+
+    /// Messages 2 and 3 are gone, and deleting 4 fails.
+    fn cancelling_service() -> TestServer {
+        TestServer::with_handler(|request| match request.path.as_str() {
+            "/m/1" => Response::new(204),
+            "/m/2" => Response::new(410),
+            "/m/3" => Response::new(404),
+            _ => Response::new(500).header("Retry-After", "30"),
+        })
+    }
+
+    #[test]
+    fn test_cancel() {
+        each_client(|kind| {
+            let server = cancelling_service();
+            let client = kind.client();
+
+            let results: Vec<_> = (1..=4)
+                .map(|id| client.cancel(&server.url(&format!("/m/{}", id))))
+                .collect();
+
+            assert_eq!(
+                vec![
+                    Ok(Cancellation::Cancelled),
+                    Ok(Cancellation::DeliveredOrExpired),
+                    Ok(Cancellation::DeliveredOrExpired),
+                    Err(WebPushError::ServerError(Some(Duration::from_secs(30)))),
+                ],
+                results
+            );
+
+            assert!(server
+                .requests()
+                .iter()
+                .all(|request| request.method == "DELETE"));
+        });
+    }
+
+    const TIMEOUT: Duration = Duration::from_millis(100);
+
+    /// Holds every answer until the stall is released.
+    fn stalled_service(stall: &Stall) -> TestServer {
+        let stall = stall.clone();
+
+        TestServer::with_handler(move |_| {
+            stall.wait();
+            Response::new(201)
+        })
+    }
+
+    fn timeout_message(endpoint: &str, timeout: Option<Duration>) -> WebPushMessage {
+        message(endpoint, |builder| {
+            if let Some(timeout) = timeout {
+                builder.set_timeout(timeout);
+            }
+        })
+    }
+
+    fn builders() -> (WebPushClientBuilder, WebPushClientBuilder) {
+        let mut overall = WebPushClientBuilder::new();
+        overall.set_timeout(TIMEOUT);
+        overall.set_connect_timeout(Duration::from_secs(1));
+        overall.set_user_agent("web-push-test/1.0");
+
+        let mut read = WebPushClientBuilder::new();
+        read.set_read_timeout(TIMEOUT);
+        read.set_pool_max_idle_per_host(1);
+        read.set_pool_idle_timeout(Duration::from_secs(1));
+
+        (overall, read)
+    }
+
+    #[test]
+    fn test_timeouts() {
+        each_client(|kind| {
+            let stall = Stall::new();
+            let server = stalled_service(&stall);
+            let endpoint = server.url("/push");
+            let (overall, read) = builders();
+
+            let overall = kind.build(&overall);
+            assert_eq!(
+                Err(WebPushError::Timeout),
+                overall.send(timeout_message(&endpoint, None))
+            );
+
+            assert_eq!(
+                Err(WebPushError::Timeout),
+                kind.build(&read).send(timeout_message(&endpoint, None))
+            );
+
+            let client = kind.client();
+            assert_eq!(
+                Err(WebPushError::Timeout),
+                client.send(timeout_message(&endpoint, Some(TIMEOUT)))
+            );
+
+            // The longer timeout of the message wins over the one of the
+            // client, answered once that has passed.
+            std::thread::scope(|scope| {
+                scope.spawn(|| {
+                    server.wait_for_requests(4);
+                    std::thread::sleep(TIMEOUT * 2);
+                    stall.release();
+                });
+
+                assert!(overall
+                    .send(timeout_message(&endpoint, Some(Duration::from_secs(5))))
+                    .is_ok());
+            });
+
+            assert!(client.send(timeout_message(&endpoint, None)).is_ok());
+
+            let with_user_agent = server
+                .requests()
+                .iter()
+                .filter(|request| request.header("User-Agent") == Some("web-push-test/1.0"))
+                .count();
+            assert_eq!(2, with_user_agent);
+        });
+    }
+
+    #[cfg(feature = "hyper")]
+    #[tokio::test]
+    async fn test_tokio_client_poll_receipts_timeout() {
+        let stall = Stall::new();
+        let server = stalled_service(&stall);
+        let client = builders().0.build_tokio();
+
+        assert_eq!(
+            Err(WebPushError::Timeout),
+            client
+                .poll_receipts(&ReceiptSubscription::new(server.url("/receipts/r1")))
+                .next()
+                .await
+        );
+
+        stall.release();
+    }
+
+    /// Fails the first two messages to `/flaky`, and every message to the
+    /// other paths.
+    fn scripted_service() -> TestServer {
+        let flaky = AtomicUsize::new(0);
+
+        TestServer::with_handler(move |request| match request.path.as_str() {
+            "/flaky" if flaky.fetch_add(1, Ordering::SeqCst) < 2 => {
+                Response::new(503).header("Retry-After", "0")
+            }
+            "/flaky" => Response::new(201),
+            "/busy" => Response::new(429).header("Retry-After", "5"),
+            "/invalid" => Response::new(400),
+            _ => Response::new(500),
+        })
+    }
+
+    fn retrying_builder() -> WebPushClientBuilder {
+        let mut policy = RetryPolicy::new(3);
+        policy.set_backoff(Duration::from_millis(10), Duration::from_millis(50));
+        policy.set_jitter(0.0);
+        policy.set_deadline(Duration::from_secs(1));
+
+        let mut builder = WebPushClientBuilder::new();
+        builder.set_retry_policy(policy);
+        builder
+    }
+
+    #[test]
+    fn test_retries() {
+        each_client(|kind| {
+            let server = scripted_service();
+            let client = kind.build(&retrying_builder());
+
+            let mut results = ["/flaky", "/down", "/invalid", "/busy"]
+                .iter()
+                .map(|path| client.send(topic_message(&server.url(path), "retry")));
+
+            assert_eq!(3, results.next().unwrap().unwrap().attempts);
+            assert_eq!(Some(Err(WebPushError::ServerError(None))), results.next());
+            assert!(matches!(
+                results.next(),
+                Some(Err(WebPushError::BadRequest(_)))
+            ));
+            assert_eq!(
+                Some(Err(WebPushError::TooManyRequests(Some(
+                    Duration::from_secs(5)
+                )))),
+                results.next()
+            );
+
+            let requests = server.requests();
+            let to = |path| requests.iter().filter(move |request| request.path == path);
+
+            // The same encrypted message is sent on every attempt.
+            let flaky: Vec<_> = to("/flaky").map(|request| &request.body).collect();
+            assert_eq!(3, flaky.len());
+            assert!(!flaky[0].is_empty());
+            assert!(flaky.iter().all(|body| *body == flaky[0]));
+
+            assert_eq!(3, to("/down").count());
+            assert_eq!(1, to("/invalid").count());
+            // Retry-After is over the maximum backoff, and past the deadline.
+            assert_eq!(1, to("/busy").count());
+        });
+    }
+}
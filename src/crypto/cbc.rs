@@ -0,0 +1,42 @@
+use crate::error::WebPushError;
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockCipher, BlockDecryptMut, KeyInit, KeyIvInit};
+use aes::{Aes128, Aes192, Aes256};
+
+/// Decrypts AES-CBC with the RustCrypto `cbc` crate and removes the PKCS#7
+/// padding.
+pub(crate) fn aes_cbc_decrypt(
+    key: &[u8],
+    iv: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, WebPushError> {
+    match key.len() {
+        16 => decrypt::<Aes128>(key, iv, ciphertext),
+        24 => decrypt::<Aes192>(key, iv, ciphertext),
+        32 => decrypt::<Aes256>(key, iv, ciphertext),
+        _ => Err(WebPushError::Unspecified),
+    }
+}
+
+fn decrypt<C>(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, WebPushError>
+where
+    C: BlockCipher + BlockDecryptMut + KeyInit,
+{
+    // `is_multiple_of` needs a much newer Rust than the rest of the crate.
+    #[allow(clippy::manual_is_multiple_of)]
+    if ciphertext.is_empty() || ciphertext.len() % 16 != 0 {
+        return Err(WebPushError::InvalidPadding);
+    }
+
+    let decryptor =
+        cbc::Decryptor::<C>::new_from_slices(key, iv).map_err(|_| WebPushError::Unspecified)?;
+
+    let mut plaintext = ciphertext.to_vec();
+    let len = decryptor
+        .decrypt_padded_mut::<Pkcs7>(&mut plaintext)
+        .map_err(|_| WebPushError::InvalidPadding)?
+        .len();
+    plaintext.truncate(len);
+
+    Ok(plaintext)
+}
@@ -0,0 +1,36 @@
+use crate::error::WebPushError;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+
+fn secret_key(private_key: &[u8]) -> Result<SecretKey, WebPushError> {
+    if private_key.len() != 32 {
+        return Err(WebPushError::InvalidCryptoKeys);
+    }
+
+    SecretKey::from_slice(private_key).map_err(|_| WebPushError::InvalidCryptoKeys)
+}
+
+/// The uncompressed public key of a P-256 private key.
+pub(crate) fn public_key(private_key: &[u8]) -> Result<Vec<u8>, WebPushError> {
+    let public_key = secret_key(private_key)?.public_key();
+
+    Ok(public_key.to_encoded_point(false).as_bytes().to_vec())
+}
+
+/// The x coordinate of the shared point of a P-256 key agreement.
+pub(crate) fn ecdh(private_key: &[u8], peer_public_key: &[u8]) -> Result<Vec<u8>, WebPushError> {
+    let secret_key = secret_key(private_key)?;
+    let peer_public_key =
+        PublicKey::from_sec1_bytes(peer_public_key).map_err(|_| WebPushError::InvalidCryptoKeys)?;
+
+    let shared_secret =
+        p256::ecdh::diffie_hellman(secret_key.to_nonzero_scalar(), peer_public_key.as_affine());
+
+    Ok(shared_secret.raw_secret_bytes().to_vec())
+}
+
+/// The private key as a RustCrypto signing key.
+#[cfg(feature = "backend-rustcrypto")]
+pub(crate) fn signing_key(private_key: &[u8]) -> Result<p256::ecdsa::SigningKey, WebPushError> {
+    Ok(p256::ecdsa::SigningKey::from(secret_key(private_key)?))
+}
@@ -0,0 +1,499 @@
+//! The cryptographic primitives for payload encryption and VAPID, behind a
+//! backend chosen with a cargo feature:
+//!
+//! * `backend-ring` (default) uses [ring](https://crates.io/crates/ring)
+//! * `backend-openssl` uses [OpenSSL](https://crates.io/crates/openssl)
+//! * `backend-rustcrypto` uses the pure Rust [RustCrypto](https://github.com/RustCrypto) crates
+//!
+//! If more than one is enabled, RustCrypto is preferred over OpenSSL, and
+//! OpenSSL over ring, so a non-default backend can be picked without
+//! disabling the default features.
+
+use crate::error::WebPushError;
+
+// Backends which are enabled but not used are only built for their tests.
+#[cfg(all(
+    feature = "backend-openssl",
+    any(test, not(feature = "backend-rustcrypto"))
+))]
+mod openssl;
+#[cfg(all(
+    feature = "backend-ring",
+    any(
+        test,
+        not(any(feature = "backend-openssl", feature = "backend-rustcrypto"))
+    )
+))]
+mod ring;
+#[cfg(feature = "backend-rustcrypto")]
+mod rustcrypto;
+
+// Ring has no AES-CBC, the RustCrypto block cipher is used for it instead.
+#[cfg(any(
+    feature = "backend-rustcrypto",
+    all(feature = "backend-ring", any(test, not(feature = "backend-openssl")))
+))]
+mod cbc;
+// Ring can not import P-256 private keys, the RustCrypto curve arithmetic is
+// used for deriving public keys and ECDH with them instead.
+#[cfg(any(
+    feature = "backend-rustcrypto",
+    all(feature = "backend-ring", any(test, not(feature = "backend-openssl")))
+))]
+mod ec;
+
+#[cfg(not(any(
+    feature = "backend-ring",
+    feature = "backend-openssl",
+    feature = "backend-rustcrypto"
+)))]
+compile_error!(
+    "one of the features backend-ring, backend-openssl or backend-rustcrypto must be enabled"
+);
+
+/// The backend used by the crate.
+#[cfg(feature = "backend-rustcrypto")]
+pub(crate) type Backend = self::rustcrypto::RustCrypto;
+
+/// The backend used by the crate.
+#[cfg(all(feature = "backend-openssl", not(feature = "backend-rustcrypto")))]
+pub(crate) type Backend = self::openssl::OpenSsl;
+
+/// The backend used by the crate.
+#[cfg(all(
+    feature = "backend-ring",
+    not(any(feature = "backend-openssl", feature = "backend-rustcrypto"))
+))]
+pub(crate) type Backend = self::ring::Ring;
+
+/// Everything needed from a cryptographic library. Private keys are P-256
+/// scalars as 32 big-endian bytes, public keys uncompressed points of 65
+/// bytes.
+pub(crate) trait CryptoBackend {
+    /// Fills the buffer from a cryptographically secure random generator.
+    fn fill_random(dest: &mut [u8]) -> Result<(), WebPushError>;
+
+    /// The public key of a private key, fails with `InvalidCryptoKeys` if
+    /// the scalar is not a valid private key.
+    fn public_key(private_key: &[u8]) -> Result<Vec<u8>, WebPushError>;
+
+    /// Is the public key the one of the private key. Fails with
+    /// `InvalidCryptoKeys` if the scalar is not a valid private key.
+    fn is_key_pair(private_key: &[u8], public_key: &[u8]) -> Result<bool, WebPushError> {
+        Ok(Self::public_key(private_key)? == public_key)
+    }
+
+    /// Generates a new random private key with its public key.
+    fn generate_key_pair() -> Result<(Vec<u8>, Vec<u8>), WebPushError> {
+        let mut private_key = vec![0u8; 32];
+
+        // A random scalar is only rarely out of the curve order, same as in
+        // ring we give up after a hundred tries.
+        for _ in 0..100 {
+            Self::fill_random(&mut private_key)?;
+
+            if let Ok(public_key) = Self::public_key(&private_key) {
+                return Ok((private_key, public_key));
+            }
+        }
+
+        Err(WebPushError::Unspecified)
+    }
+
+    /// The shared secret of an ECDH key agreement, the x coordinate of the
+    /// shared point.
+    fn ecdh(private_key: &[u8], peer_public_key: &[u8]) -> Result<Vec<u8>, WebPushError>;
+
+    /// An ECDH key agreement with a new random private key, returning the
+    /// public key and the shared secret.
+    fn ephemeral_ecdh(peer_public_key: &[u8]) -> Result<(Vec<u8>, Vec<u8>), WebPushError> {
+        let (private_key, public_key) = Self::generate_key_pair()?;
+
+        Ok((public_key, Self::ecdh(&private_key, peer_public_key)?))
+    }
+
+    /// HKDF-SHA256 extract and expand, filling `okm` of at most 32 bytes.
+    fn hkdf_sha256(
+        salt: &[u8],
+        ikm: &[u8],
+        info: &[u8],
+        okm: &mut [u8],
+    ) -> Result<(), WebPushError>;
+
+    /// Encrypts the content in place with AES-128-GCM, appending the tag.
+    fn aes_128_gcm_seal(
+        key: &[u8],
+        nonce: &[u8; 12],
+        content: &mut Vec<u8>,
+    ) -> Result<(), WebPushError>;
+
+    /// Decrypts content followed by the tag in place, returning the length of
+    /// the plaintext. Fails with `InvalidAuthenticationTag`.
+    fn aes_128_gcm_open(
+        key: &[u8],
+        nonce: &[u8; 12],
+        content: &mut [u8],
+    ) -> Result<usize, WebPushError>;
+
+    /// Signs the message with ECDSA P-256 and SHA-256, returning the
+    /// signature as `r || s`. The public key has to be the one of the private
+    /// key.
+    fn es256_sign(
+        private_key: &[u8],
+        public_key: &[u8],
+        message: &[u8],
+    ) -> Result<[u8; 64], WebPushError>;
+
+    /// PBKDF2 with HMAC-SHA256, filling `okm`.
+    fn pbkdf2_hmac_sha256(
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+        okm: &mut [u8],
+    ) -> Result<(), WebPushError>;
+
+    /// Decrypts AES-CBC and removes the PKCS#7 padding, the length of the key
+    /// chooses between AES-128, AES-192 and AES-256. Fails with
+    /// `InvalidPadding` if the padding is wrong.
+    fn aes_cbc_decrypt(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, WebPushError>;
+
+    /// Verifies an `r || s` signature made with `es256_sign`.
+    fn es256_verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool;
+}
+
+/// The `len` bytes of HKDF-SHA256 output.
+pub(crate) fn hkdf_sha256(
+    salt: &[u8],
+    ikm: &[u8],
+    info: &[u8],
+    len: usize,
+) -> Result<Vec<u8>, WebPushError> {
+    let mut okm = vec![0u8; len];
+    Backend::hkdf_sha256(salt, ikm, info, &mut okm)?;
+
+    Ok(okm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CryptoBackend;
+    use crate::error::WebPushError;
+    use base64::{self, URL_SAFE_NO_PAD};
+
+    fn decode(data: &str) -> Vec<u8> {
+        base64::decode_config(data, URL_SAFE_NO_PAD).unwrap()
+    }
+
+    fn hex(data: &str) -> Vec<u8> {
+        (0..data.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&data[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // RFC 8291, Appendix A
+    const AS_PRIVATE: &str = "yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw";
+    const AS_PUBLIC: &str =
+        "BP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A8";
+    const UA_PRIVATE: &str = "q1dXpw3UpT5VOmu_cf_v6ih07Aems3njxI-JWgLcM94";
+    const UA_PUBLIC: &str =
+        "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4";
+    const SHARED_SECRET: &str = "kyrL1jIIOHEzg3sM2ZWRHDRB62YACZhhSlknJ672kSs";
+
+    fn public_key<B: CryptoBackend>() {
+        assert_eq!(
+            decode(AS_PUBLIC),
+            B::public_key(&decode(AS_PRIVATE)).unwrap()
+        );
+        assert_eq!(
+            decode(UA_PUBLIC),
+            B::public_key(&decode(UA_PRIVATE)).unwrap()
+        );
+
+        assert_eq!(
+            Err(WebPushError::InvalidCryptoKeys),
+            B::public_key(&[0u8; 32])
+        );
+        assert_eq!(
+            Err(WebPushError::InvalidCryptoKeys),
+            B::public_key(&[0xffu8; 32])
+        );
+        assert_eq!(
+            Err(WebPushError::InvalidCryptoKeys),
+            B::public_key(&[1u8; 31])
+        );
+    }
+
+    fn ecdh<B: CryptoBackend>() {
+        assert_eq!(
+            decode(SHARED_SECRET),
+            B::ecdh(&decode(AS_PRIVATE), &decode(UA_PUBLIC)).unwrap()
+        );
+        assert_eq!(
+            decode(SHARED_SECRET),
+            B::ecdh(&decode(UA_PRIVATE), &decode(AS_PUBLIC)).unwrap()
+        );
+
+        let mut not_on_curve = decode(UA_PUBLIC);
+        not_on_curve[64] ^= 1;
+
+        assert_eq!(
+            Err(WebPushError::InvalidCryptoKeys),
+            B::ecdh(&decode(AS_PRIVATE), &not_on_curve)
+        );
+    }
+
+    fn hkdf<B: CryptoBackend>() {
+        // RFC 5869, A.1 truncated to one block
+        let mut okm = [0u8; 32];
+
+        B::hkdf_sha256(
+            &hex("000102030405060708090a0b0c"),
+            &hex("0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b"),
+            &hex("f0f1f2f3f4f5f6f7f8f9"),
+            &mut okm,
+        )
+        .unwrap();
+
+        assert_eq!(
+            hex("3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf"),
+            okm.to_vec()
+        );
+
+        // RFC 8291, Appendix A
+        let mut ikm = [0u8; 32];
+        let mut info = b"WebPush: info\0".to_vec();
+        info.extend_from_slice(&decode(UA_PUBLIC));
+        info.extend_from_slice(&decode(AS_PUBLIC));
+
+        B::hkdf_sha256(
+            &decode("BTBZMqHH6r4Tts7J_aSIgg"),
+            &decode(SHARED_SECRET),
+            &info,
+            &mut ikm,
+        )
+        .unwrap();
+
+        assert_eq!(
+            decode("S4lYMb_L0FxCeq0WhDx813KgSYqU26kOyzWUdsXYyrg"),
+            ikm.to_vec()
+        );
+    }
+
+    fn aes_128_gcm<B: CryptoBackend>() {
+        // RFC 8188, 3.1
+        let key = decode("_wniytB-ofscZDh4tbSjHw");
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&decode("Bcs8gkIRKLI8GeI8"));
+
+        let mut content = b"I am the walrus\x02".to_vec();
+        B::aes_128_gcm_seal(&key, &nonce, &mut content).unwrap();
+
+        assert_eq!(
+            decode("-NAVub2qFgBEuQKRapoZu-IxkIva3MEB1PD-ly8Thjg"),
+            content
+        );
+
+        let len = B::aes_128_gcm_open(&key, &nonce, &mut content).unwrap();
+        assert_eq!(b"I am the walrus\x02", &content[..len]);
+
+        let mut content = decode("-NAVub2qFgBEuQKRapoZu-IxkIva3MEB1PD-ly8Thjg");
+        content[0] ^= 1;
+
+        assert_eq!(
+            Err(WebPushError::InvalidAuthenticationTag),
+            B::aes_128_gcm_open(&key, &nonce, &mut content)
+        );
+        assert_eq!(
+            Err(WebPushError::InvalidAuthenticationTag),
+            B::aes_128_gcm_open(&key, &nonce, &mut [0u8; 15])
+        );
+    }
+
+    fn es256<B: CryptoBackend>() {
+        let private_key = decode(AS_PRIVATE);
+        let public_key = decode(AS_PUBLIC);
+
+        let signature = B::es256_sign(&private_key, &public_key, b"I am the walrus").unwrap();
+
+        assert!(B::es256_verify(&public_key, b"I am the walrus", &signature));
+        assert!(!B::es256_verify(
+            &public_key,
+            b"I am the eggman",
+            &signature
+        ));
+        assert!(!B::es256_verify(
+            &decode(UA_PUBLIC),
+            b"I am the walrus",
+            &signature
+        ));
+        assert!(!B::es256_verify(
+            &public_key,
+            b"I am the walrus",
+            &signature[..63]
+        ));
+
+        // Made with the `cryptography` Python package
+        let signature = decode(
+            "hkrrZPhJF-B8Y10w9dRAhxoeEtD9JiZESmJXvibDykE2IOuJXaB9ATk9GdiEiCLjypU_VXEZe6XGN_AXXZTFNg",
+        );
+
+        assert!(B::es256_verify(&public_key, b"I am the walrus", &signature));
+    }
+
+    fn pbkdf2<B: CryptoBackend>() {
+        // RFC 7914, section 11
+        let mut okm = [0u8; 64];
+        B::pbkdf2_hmac_sha256(b"passwd", b"salt", 1, &mut okm).unwrap();
+
+        assert_eq!(
+            hex("55ac046e56e3089fec1691c22544b605f94185216dde0465e68b9d57c20dacbc49ca9cccf179b645991664b39d77ef317c71b845b1e30bd509112041d3a19783"),
+            okm.to_vec()
+        );
+
+        let mut okm = [0u8; 32];
+        B::pbkdf2_hmac_sha256(b"Password", b"NaCl", 80000, &mut okm).unwrap();
+
+        assert_eq!(
+            hex("4ddcd8f60b98be21830cee5ef22701f9641a4418d04c0414aeff08876b34ab56"),
+            okm.to_vec()
+        );
+    }
+
+    fn aes_cbc<B: CryptoBackend>() {
+        // Made with the `cryptography` Python package
+        let key: Vec<u8> = (0..32).collect();
+        let iv: Vec<u8> = (16..32).collect();
+
+        assert_eq!(
+            b"I am the walrus, goo goo g".to_vec(),
+            B::aes_cbc_decrypt(
+                &key,
+                &iv,
+                &hex("5d0821b8dc57d8620fac901c9f92ad660822facbdd711f5a3112122c1c835c4a")
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            b"I am the walrus, goo goo g".to_vec(),
+            B::aes_cbc_decrypt(
+                &key[..16],
+                &iv,
+                &hex("558dd7314d7d410c039f5876c49a5dbdb7edfed4196eb8c3b62a2d2aca3664d8")
+            )
+            .unwrap()
+        );
+
+        // Decrypts with the wrong key to garbage padding
+        assert_eq!(
+            Err(WebPushError::InvalidPadding),
+            B::aes_cbc_decrypt(
+                &key[..16],
+                &iv,
+                &hex("5d0821b8dc57d8620fac901c9f92ad660822facbdd711f5a3112122c1c835c4a")
+            )
+        );
+        assert_eq!(
+            Err(WebPushError::InvalidPadding),
+            B::aes_cbc_decrypt(&key, &iv, &[0u8; 17])
+        );
+    }
+
+    fn generate<B: CryptoBackend>() {
+        let mut first = [0u8; 32];
+        let mut second = [0u8; 32];
+
+        B::fill_random(&mut first).unwrap();
+        B::fill_random(&mut second).unwrap();
+
+        assert_ne!(first, second);
+
+        let (private_key, public_key) = B::generate_key_pair().unwrap();
+        assert_eq!(32, private_key.len());
+        assert_eq!(65, public_key.len());
+        assert!(B::is_key_pair(&private_key, &public_key).unwrap());
+        assert!(!B::is_key_pair(&private_key, &decode(AS_PUBLIC)).unwrap());
+        assert_eq!(
+            Err(WebPushError::InvalidCryptoKeys),
+            B::is_key_pair(&[0u8; 32], &public_key)
+        );
+
+        let signature = B::es256_sign(&private_key, &public_key, b"I am the walrus").unwrap();
+        assert!(B::es256_verify(&public_key, b"I am the walrus", &signature));
+
+        let (public_key, shared_secret) = B::ephemeral_ecdh(&decode(UA_PUBLIC)).unwrap();
+        assert_eq!(65, public_key.len());
+        assert_eq!(32, shared_secret.len());
+        assert_ne!(public_key, B::ephemeral_ecdh(&decode(UA_PUBLIC)).unwrap().0);
+    }
+
+    fn agreement_with_generated_key<B: CryptoBackend>() {
+        let (public_key, shared_secret) = B::ephemeral_ecdh(&decode(UA_PUBLIC)).unwrap();
+
+        assert_eq!(
+            shared_secret,
+            B::ecdh(&decode(UA_PRIVATE), &public_key).unwrap()
+        );
+        assert!(B::is_key_pair(&decode(AS_PRIVATE), &decode(AS_PUBLIC)).unwrap());
+    }
+
+    macro_rules! backend_tests {
+        ($name:ident, $feature:literal, $backend:ty) => {
+            #[cfg(feature = $feature)]
+            mod $name {
+                #[test]
+                fn test_hkdf() {
+                    super::hkdf::<$backend>();
+                }
+
+                #[test]
+                fn test_aes_128_gcm() {
+                    super::aes_128_gcm::<$backend>();
+                }
+
+                #[test]
+                fn test_es256() {
+                    super::es256::<$backend>();
+                }
+
+                #[test]
+                fn test_pbkdf2() {
+                    super::pbkdf2::<$backend>();
+                }
+
+                #[test]
+                fn test_aes_cbc() {
+                    super::aes_cbc::<$backend>();
+                }
+
+                #[test]
+                fn test_generate() {
+                    super::generate::<$backend>();
+                }
+
+                #[test]
+                fn test_public_key() {
+                    super::public_key::<$backend>();
+                }
+
+                #[test]
+                fn test_ecdh() {
+                    super::ecdh::<$backend>();
+                }
+
+                #[test]
+                fn test_agreement_with_generated_key() {
+                    super::agreement_with_generated_key::<$backend>();
+                }
+            }
+        };
+    }
+
+    backend_tests!(ring, "backend-ring", crate::crypto::ring::Ring);
+    backend_tests!(openssl, "backend-openssl", crate::crypto::openssl::OpenSsl);
+    backend_tests!(
+        rustcrypto,
+        "backend-rustcrypto",
+        crate::crypto::rustcrypto::RustCrypto
+    );
+}
@@ -0,0 +1,236 @@
+use super::CryptoBackend;
+use crate::error::WebPushError;
+use ::openssl::bn::{BigNum, BigNumContext};
+use ::openssl::derive::Deriver;
+use ::openssl::ec::{EcGroup, EcKey, EcPoint, PointConversionForm};
+use ::openssl::ecdsa::EcdsaSig;
+use ::openssl::error::ErrorStack;
+use ::openssl::hash::{hash, MessageDigest};
+use ::openssl::nid::Nid;
+use ::openssl::pkcs5;
+use ::openssl::pkey::{PKey, Private, Public};
+use ::openssl::sign::Signer;
+use ::openssl::symm::{self, Cipher};
+
+pub(crate) struct OpenSsl;
+
+lazy_static! {
+    static ref GROUP: EcGroup =
+        EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).expect("EC Prime256v1 not supported");
+}
+
+fn private_key(private_key: &[u8]) -> Result<EcKey<Private>, WebPushError> {
+    if private_key.len() != 32 {
+        return Err(WebPushError::InvalidCryptoKeys);
+    }
+
+    let invalid = |_| WebPushError::InvalidCryptoKeys;
+
+    let mut ctx = BigNumContext::new().map_err(invalid)?;
+    let mut order = BigNum::new().map_err(invalid)?;
+    GROUP.order(&mut order, &mut ctx).map_err(invalid)?;
+
+    let scalar = BigNum::from_slice(private_key).map_err(invalid)?;
+
+    if scalar.num_bits() == 0 || scalar >= order {
+        return Err(WebPushError::InvalidCryptoKeys);
+    }
+
+    let mut point = EcPoint::new(&GROUP).map_err(invalid)?;
+    point
+        .mul_generator2(&GROUP, &scalar, &mut ctx)
+        .map_err(invalid)?;
+
+    EcKey::from_private_components(&GROUP, &scalar, &point).map_err(invalid)
+}
+
+fn public_key(public_key: &[u8]) -> Result<EcKey<Public>, WebPushError> {
+    let invalid = |_| WebPushError::InvalidCryptoKeys;
+
+    let mut ctx = BigNumContext::new().map_err(invalid)?;
+    let point = EcPoint::from_bytes(&GROUP, public_key, &mut ctx).map_err(invalid)?;
+
+    EcKey::from_public_key(&GROUP, &point).map_err(invalid)
+}
+
+fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> Result<Vec<u8>, WebPushError> {
+    let key = PKey::hmac(key).map_err(|_| WebPushError::Unspecified)?;
+    let mut signer =
+        Signer::new(MessageDigest::sha256(), &key).map_err(|_| WebPushError::Unspecified)?;
+
+    for part in data {
+        signer.update(part).map_err(|_| WebPushError::Unspecified)?;
+    }
+
+    signer.sign_to_vec().map_err(|_| WebPushError::Unspecified)
+}
+
+impl CryptoBackend for OpenSsl {
+    fn fill_random(dest: &mut [u8]) -> Result<(), WebPushError> {
+        ::openssl::rand::rand_bytes(dest).map_err(|_| WebPushError::Unspecified)
+    }
+
+    fn public_key(private_key: &[u8]) -> Result<Vec<u8>, WebPushError> {
+        let key = self::private_key(private_key)?;
+        let mut ctx = BigNumContext::new().map_err(|_| WebPushError::Unspecified)?;
+
+        key.public_key()
+            .to_bytes(&GROUP, PointConversionForm::UNCOMPRESSED, &mut ctx)
+            .map_err(|_| WebPushError::InvalidCryptoKeys)
+    }
+
+    fn ecdh(private_key: &[u8], peer_public_key: &[u8]) -> Result<Vec<u8>, WebPushError> {
+        let invalid = |_| WebPushError::InvalidCryptoKeys;
+
+        let private_key = PKey::from_ec_key(self::private_key(private_key)?).map_err(invalid)?;
+        let peer_public_key =
+            PKey::from_ec_key(self::public_key(peer_public_key)?).map_err(invalid)?;
+
+        let mut deriver = Deriver::new(&private_key).map_err(invalid)?;
+        deriver.set_peer(&peer_public_key).map_err(invalid)?;
+
+        deriver.derive_to_vec().map_err(invalid)
+    }
+
+    fn hkdf_sha256(
+        salt: &[u8],
+        ikm: &[u8],
+        info: &[u8],
+        okm: &mut [u8],
+    ) -> Result<(), WebPushError> {
+        if okm.len() > 32 {
+            return Err(WebPushError::Unspecified);
+        }
+
+        let prk = hmac_sha256(salt, &[ikm])?;
+        let expanded = hmac_sha256(&prk, &[info, &[1]])?;
+
+        okm.copy_from_slice(&expanded[..okm.len()]);
+
+        Ok(())
+    }
+
+    fn aes_128_gcm_seal(
+        key: &[u8],
+        nonce: &[u8; 12],
+        content: &mut Vec<u8>,
+    ) -> Result<(), WebPushError> {
+        let mut tag = [0u8; 16];
+
+        let ciphertext = symm::encrypt_aead(
+            Cipher::aes_128_gcm(),
+            key,
+            Some(nonce),
+            &[],
+            content,
+            &mut tag,
+        )
+        .map_err(|_| WebPushError::Unspecified)?;
+
+        content.clear();
+        content.extend_from_slice(&ciphertext);
+        content.extend_from_slice(&tag);
+
+        Ok(())
+    }
+
+    fn aes_128_gcm_open(
+        key: &[u8],
+        nonce: &[u8; 12],
+        content: &mut [u8],
+    ) -> Result<usize, WebPushError> {
+        if content.len() < 16 {
+            return Err(WebPushError::InvalidAuthenticationTag);
+        }
+
+        let (ciphertext, tag) = content.split_at(content.len() - 16);
+
+        let plaintext = symm::decrypt_aead(
+            Cipher::aes_128_gcm(),
+            key,
+            Some(nonce),
+            &[],
+            ciphertext,
+            tag,
+        )
+        .map_err(|_| WebPushError::InvalidAuthenticationTag)?;
+
+        content[..plaintext.len()].copy_from_slice(&plaintext);
+
+        Ok(plaintext.len())
+    }
+
+    fn es256_sign(
+        private_key: &[u8],
+        _public_key: &[u8],
+        message: &[u8],
+    ) -> Result<[u8; 64], WebPushError> {
+        let key = self::private_key(private_key)?;
+        let digest =
+            hash(MessageDigest::sha256(), message).map_err(|_| WebPushError::Unspecified)?;
+        let signature = EcdsaSig::sign(&digest, &key).map_err(|_| WebPushError::Unspecified)?;
+
+        let r = signature
+            .r()
+            .to_vec_padded(32)
+            .map_err(|_| WebPushError::Unspecified)?;
+        let s = signature
+            .s()
+            .to_vec_padded(32)
+            .map_err(|_| WebPushError::Unspecified)?;
+
+        let mut fixed = [0u8; 64];
+        fixed[..32].copy_from_slice(&r);
+        fixed[32..].copy_from_slice(&s);
+
+        Ok(fixed)
+    }
+
+    fn pbkdf2_hmac_sha256(
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+        okm: &mut [u8],
+    ) -> Result<(), WebPushError> {
+        pkcs5::pbkdf2_hmac(
+            password,
+            salt,
+            iterations as usize,
+            MessageDigest::sha256(),
+            okm,
+        )
+        .map_err(|_| WebPushError::Unspecified)
+    }
+
+    fn aes_cbc_decrypt(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, WebPushError> {
+        let cipher = match key.len() {
+            16 => Cipher::aes_128_cbc(),
+            24 => Cipher::aes_192_cbc(),
+            32 => Cipher::aes_256_cbc(),
+            _ => return Err(WebPushError::Unspecified),
+        };
+
+        symm::decrypt(cipher, key, Some(iv), ciphertext).map_err(|_| WebPushError::InvalidPadding)
+    }
+
+    fn es256_verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        if signature.len() != 64 {
+            return false;
+        }
+
+        let key = match self::public_key(public_key) {
+            Ok(key) => key,
+            Err(_) => return false,
+        };
+
+        let verify = || -> Result<bool, ErrorStack> {
+            let r = BigNum::from_slice(&signature[..32])?;
+            let s = BigNum::from_slice(&signature[32..])?;
+            let digest = hash(MessageDigest::sha256(), message)?;
+
+            EcdsaSig::from_private_components(r, s)?.verify(&digest, &key)
+        };
+
+        verify().unwrap_or(false)
+    }
+}
@@ -0,0 +1,193 @@
+use super::{ec, CryptoBackend};
+use crate::error::WebPushError;
+use ::ring::rand::SecureRandom;
+use ::ring::{aead, agreement, hkdf, pbkdf2, rand, signature};
+use std::num::NonZeroU32;
+
+pub(crate) struct Ring;
+
+/// A PKCS#8 document for a P-256 key, up to the 32 byte private key and the
+/// 65 byte public key. Ring can only import signing keys from PKCS#8, with
+/// the public key included.
+const PKCS8_PREFIX: &[u8] = &[
+    0x30, 0x81, 0x87, 0x02, 0x01, 0x00, 0x30, 0x13, 0x06, 0x07, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02,
+    0x01, 0x06, 0x08, 0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07, 0x04, 0x6d, 0x30, 0x6b, 0x02,
+    0x01, 0x01, 0x04, 0x20,
+];
+
+const PKCS8_PUBLIC_KEY: &[u8] = &[0xa1, 0x44, 0x03, 0x42, 0x00];
+
+fn pkcs8(private_key: &[u8], public_key: &[u8]) -> Vec<u8> {
+    let mut pkcs8 = PKCS8_PREFIX.to_vec();
+    pkcs8.extend_from_slice(private_key);
+    pkcs8.extend_from_slice(PKCS8_PUBLIC_KEY);
+    pkcs8.extend_from_slice(public_key);
+
+    pkcs8
+}
+
+fn signing_key(
+    private_key: &[u8],
+    public_key: &[u8],
+) -> Result<signature::EcdsaKeyPair, WebPushError> {
+    signature::EcdsaKeyPair::from_pkcs8(
+        &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+        &pkcs8(private_key, public_key),
+    )
+    .map_err(|_| WebPushError::InvalidCryptoKeys)
+}
+
+fn aead_key(key: &[u8]) -> Result<aead::LessSafeKey, WebPushError> {
+    let key =
+        aead::UnboundKey::new(&aead::AES_128_GCM, key).map_err(|_| WebPushError::Unspecified)?;
+
+    Ok(aead::LessSafeKey::new(key))
+}
+
+struct OkmLen(usize);
+
+impl hkdf::KeyType for OkmLen {
+    fn len(&self) -> usize {
+        self.0
+    }
+}
+
+impl CryptoBackend for Ring {
+    fn fill_random(dest: &mut [u8]) -> Result<(), WebPushError> {
+        rand::SystemRandom::new()
+            .fill(dest)
+            .map_err(|_| WebPushError::Unspecified)
+    }
+
+    fn public_key(private_key: &[u8]) -> Result<Vec<u8>, WebPushError> {
+        ec::public_key(private_key)
+    }
+
+    fn generate_key_pair() -> Result<(Vec<u8>, Vec<u8>), WebPushError> {
+        let document = signature::EcdsaKeyPair::generate_pkcs8(
+            &signature::ECDSA_P256_SHA256_FIXED_SIGNING,
+            &rand::SystemRandom::new(),
+        )
+        .map_err(|_| WebPushError::Unspecified)?;
+
+        let document = document.as_ref();
+        let public_key_start = PKCS8_PREFIX.len() + 32 + PKCS8_PUBLIC_KEY.len();
+
+        if document.len() != public_key_start + 65 || !document.starts_with(PKCS8_PREFIX) {
+            return Err(WebPushError::Unspecified);
+        }
+
+        Ok((
+            document[PKCS8_PREFIX.len()..PKCS8_PREFIX.len() + 32].to_vec(),
+            document[public_key_start..].to_vec(),
+        ))
+    }
+
+    fn ecdh(private_key: &[u8], peer_public_key: &[u8]) -> Result<Vec<u8>, WebPushError> {
+        ec::ecdh(private_key, peer_public_key)
+    }
+
+    fn ephemeral_ecdh(peer_public_key: &[u8]) -> Result<(Vec<u8>, Vec<u8>), WebPushError> {
+        let private_key = agreement::EphemeralPrivateKey::generate(
+            &agreement::ECDH_P256,
+            &rand::SystemRandom::new(),
+        )
+        .map_err(|_| WebPushError::Unspecified)?;
+        let public_key = private_key
+            .compute_public_key()
+            .map_err(|_| WebPushError::Unspecified)?;
+        let peer_public_key =
+            agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, peer_public_key);
+
+        let shared_secret = agreement::agree_ephemeral(
+            private_key,
+            &peer_public_key,
+            WebPushError::InvalidCryptoKeys,
+            |shared_secret| Ok(shared_secret.to_vec()),
+        )?;
+
+        Ok((public_key.as_ref().to_vec(), shared_secret))
+    }
+
+    fn hkdf_sha256(
+        salt: &[u8],
+        ikm: &[u8],
+        info: &[u8],
+        okm: &mut [u8],
+    ) -> Result<(), WebPushError> {
+        hkdf::Salt::new(hkdf::HKDF_SHA256, salt)
+            .extract(ikm)
+            .expand(&[info], OkmLen(okm.len()))
+            .and_then(|expanded| expanded.fill(okm))
+            .map_err(|_| WebPushError::Unspecified)
+    }
+
+    fn aes_128_gcm_seal(
+        key: &[u8],
+        nonce: &[u8; 12],
+        content: &mut Vec<u8>,
+    ) -> Result<(), WebPushError> {
+        aead_key(key)?
+            .seal_in_place_append_tag(
+                aead::Nonce::assume_unique_for_key(*nonce),
+                aead::Aad::empty(),
+                content,
+            )
+            .map_err(|_| WebPushError::Unspecified)
+    }
+
+    fn aes_128_gcm_open(
+        key: &[u8],
+        nonce: &[u8; 12],
+        content: &mut [u8],
+    ) -> Result<usize, WebPushError> {
+        let plaintext = aead_key(key)?
+            .open_in_place(
+                aead::Nonce::assume_unique_for_key(*nonce),
+                aead::Aad::empty(),
+                content,
+            )
+            .map_err(|_| WebPushError::InvalidAuthenticationTag)?;
+
+        Ok(plaintext.len())
+    }
+
+    fn es256_sign(
+        private_key: &[u8],
+        public_key: &[u8],
+        message: &[u8],
+    ) -> Result<[u8; 64], WebPushError> {
+        let key_pair = signing_key(private_key, public_key)?;
+
+        let signature = key_pair
+            .sign(&rand::SystemRandom::new(), message)
+            .map_err(|_| WebPushError::Unspecified)?;
+
+        let mut fixed = [0u8; 64];
+        fixed.copy_from_slice(signature.as_ref());
+
+        Ok(fixed)
+    }
+
+    fn pbkdf2_hmac_sha256(
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+        okm: &mut [u8],
+    ) -> Result<(), WebPushError> {
+        let iterations = NonZeroU32::new(iterations).ok_or(WebPushError::Unspecified)?;
+        pbkdf2::derive(pbkdf2::PBKDF2_HMAC_SHA256, iterations, salt, password, okm);
+
+        Ok(())
+    }
+
+    fn aes_cbc_decrypt(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, WebPushError> {
+        super::cbc::aes_cbc_decrypt(key, iv, ciphertext)
+    }
+
+    fn es256_verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_FIXED, public_key)
+            .verify(message, signature)
+            .is_ok()
+    }
+}
@@ -0,0 +1,137 @@
+use super::{ec, CryptoBackend};
+use crate::error::WebPushError;
+use aes_gcm::aead::{AeadInPlace, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce, Tag};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use p256::ecdsa::signature::{Signer, Verifier};
+use p256::ecdsa::{Signature, VerifyingKey};
+use rand_core::{OsRng, RngCore};
+use sha2::Sha256;
+
+pub(crate) struct RustCrypto;
+
+fn aead_key(key: &[u8]) -> Result<Aes128Gcm, WebPushError> {
+    Aes128Gcm::new_from_slice(key).map_err(|_| WebPushError::Unspecified)
+}
+
+impl CryptoBackend for RustCrypto {
+    fn fill_random(dest: &mut [u8]) -> Result<(), WebPushError> {
+        OsRng
+            .try_fill_bytes(dest)
+            .map_err(|_| WebPushError::Unspecified)
+    }
+
+    fn public_key(private_key: &[u8]) -> Result<Vec<u8>, WebPushError> {
+        ec::public_key(private_key)
+    }
+
+    fn ecdh(private_key: &[u8], peer_public_key: &[u8]) -> Result<Vec<u8>, WebPushError> {
+        ec::ecdh(private_key, peer_public_key)
+    }
+
+    fn hkdf_sha256(
+        salt: &[u8],
+        ikm: &[u8],
+        info: &[u8],
+        okm: &mut [u8],
+    ) -> Result<(), WebPushError> {
+        Hkdf::<Sha256>::new(Some(salt), ikm)
+            .expand(info, okm)
+            .map_err(|_| WebPushError::Unspecified)
+    }
+
+    fn aes_128_gcm_seal(
+        key: &[u8],
+        nonce: &[u8; 12],
+        content: &mut Vec<u8>,
+    ) -> Result<(), WebPushError> {
+        aead_key(key)?
+            .encrypt_in_place(Nonce::from_slice(nonce), &[], content)
+            .map_err(|_| WebPushError::Unspecified)
+    }
+
+    fn aes_128_gcm_open(
+        key: &[u8],
+        nonce: &[u8; 12],
+        content: &mut [u8],
+    ) -> Result<usize, WebPushError> {
+        if content.len() < 16 {
+            return Err(WebPushError::InvalidAuthenticationTag);
+        }
+
+        let (plaintext, tag) = content.split_at_mut(content.len() - 16);
+
+        aead_key(key)?
+            .decrypt_in_place_detached(
+                Nonce::from_slice(nonce),
+                &[],
+                plaintext,
+                Tag::from_slice(tag),
+            )
+            .map_err(|_| WebPushError::InvalidAuthenticationTag)?;
+
+        Ok(plaintext.len())
+    }
+
+    fn es256_sign(
+        private_key: &[u8],
+        _public_key: &[u8],
+        message: &[u8],
+    ) -> Result<[u8; 64], WebPushError> {
+        let signing_key = ec::signing_key(private_key)?;
+        let signature: Signature = signing_key.sign(message);
+
+        let mut fixed = [0u8; 64];
+        fixed.copy_from_slice(&signature.to_bytes());
+
+        Ok(fixed)
+    }
+
+    fn pbkdf2_hmac_sha256(
+        password: &[u8],
+        salt: &[u8],
+        iterations: u32,
+        okm: &mut [u8],
+    ) -> Result<(), WebPushError> {
+        let prf = <Hmac<Sha256> as Mac>::new_from_slice(password)
+            .map_err(|_| WebPushError::Unspecified)?;
+
+        for (i, chunk) in okm.chunks_mut(32).enumerate() {
+            let mut mac = prf.clone();
+            mac.update(salt);
+            mac.update(&(i as u32 + 1).to_be_bytes());
+
+            let mut u = mac.finalize().into_bytes();
+            let mut block = u;
+
+            for _ in 1..iterations {
+                let mut mac = prf.clone();
+                mac.update(&u);
+                u = mac.finalize().into_bytes();
+
+                block.iter_mut().zip(u.iter()).for_each(|(b, u)| *b ^= u);
+            }
+
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        }
+
+        Ok(())
+    }
+
+    fn aes_cbc_decrypt(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, WebPushError> {
+        super::cbc::aes_cbc_decrypt(key, iv, ciphertext)
+    }
+
+    fn es256_verify(public_key: &[u8], message: &[u8], signature: &[u8]) -> bool {
+        let verifying_key = match VerifyingKey::from_sec1_bytes(public_key) {
+            Ok(key) => key,
+            Err(_) => return false,
+        };
+
+        match Signature::from_slice(signature) {
+            Ok(signature) => verifying_key.verify(message, &signature).is_ok(),
+            Err(_) => false,
+        }
+    }
+}
@@ -0,0 +1,787 @@
+//! The aes128gcm [Encrypted Content-Encoding for HTTP](https://tools.ietf.org/html/rfc8188).
+//!
+//! Encrypts content with a raw key into a header followed by one or more
+//! records of at most `record_size` bytes. Web push uses this with a single
+//! record and a key derived from the subscription keys, but the encoding works
+//! for any content shared between two parties holding the same key.
+//!
+//! ```
+//! # use web_push::ece::{self, Header};
+//! # fn main() -> Result<(), web_push::WebPushError> {
+//! let key = [7u8; 16];
+//! let header = Header::new([1u8; 16], 4096, b"key-1")?;
+//!
+//! let body = ece::encrypt(&key, &header, b"I am the walrus")?;
+//!
+//! assert_eq!(b"I am the walrus".to_vec(), ece::decrypt(&key, &body)?);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::crypto::{self, Backend, CryptoBackend};
+use crate::error::WebPushError;
+use std::io::{self, Read, Write};
+
+/// The smallest record which can hold the delimiter and the authentication tag.
+pub const MIN_RECORD_SIZE: u32 = 18;
+
+/// Record size of the ciphertext added by the delimiter and the authentication tag.
+const RECORD_OVERHEAD: usize = 17;
+
+const TAG_LEN: usize = 16;
+
+/// The header block in the beginning of the encrypted content.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    /// A random salt, which must never be reused with the same key.
+    pub salt: [u8; 16],
+    /// The size of each record, including the authentication tag.
+    pub record_size: u32,
+    /// Identifies the key to the receiver, can be empty.
+    pub key_id: Vec<u8>,
+}
+
+impl Header {
+    pub fn new(salt: [u8; 16], record_size: u32, key_id: &[u8]) -> Result<Header, WebPushError> {
+        if record_size < MIN_RECORD_SIZE || key_id.len() > 255 {
+            return Err(WebPushError::InvalidRecord);
+        }
+
+        Ok(Header {
+            salt,
+            record_size,
+            key_id: key_id.to_vec(),
+        })
+    }
+
+    /// The length of the encoded header in bytes.
+    pub fn encoded_len(&self) -> usize {
+        21 + self.key_id.len()
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(self.encoded_len());
+
+        header.extend_from_slice(&self.salt);
+        header.extend_from_slice(&self.record_size.to_be_bytes());
+        header.push(self.key_id.len() as u8);
+        header.extend_from_slice(&self.key_id);
+
+        header
+    }
+
+    /// Parses the header from the beginning of the content, returning it with
+    /// the records following it.
+    pub fn parse(content: &[u8]) -> Result<(Header, &[u8]), WebPushError> {
+        if content.len() < 21 || content.len() < 21 + content[20] as usize {
+            return Err(WebPushError::InvalidRecord);
+        }
+
+        let mut salt = [0u8; 16];
+        salt.copy_from_slice(&content[..16]);
+
+        let mut record_size = [0u8; 4];
+        record_size.copy_from_slice(&content[16..20]);
+
+        let header = Header::new(
+            salt,
+            u32::from_be_bytes(record_size),
+            &content[21..21 + content[20] as usize],
+        )?;
+        let records = &content[header.encoded_len()..];
+
+        Ok((header, records))
+    }
+
+    /// Reads the header from the beginning of a stream.
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Header> {
+        let mut fixed = [0u8; 21];
+        reader.read_exact(&mut fixed)?;
+
+        let mut key_id = vec![0u8; fixed[20] as usize];
+        reader.read_exact(&mut key_id)?;
+
+        let mut content = fixed.to_vec();
+        content.extend_from_slice(&key_id);
+
+        Header::parse(&content)
+            .map(|(header, _)| header)
+            .map_err(invalid_data)
+    }
+
+    /// The most content that fits in one record.
+    fn record_capacity(&self) -> usize {
+        self.record_size as usize - RECORD_OVERHEAD
+    }
+}
+
+/// The content encryption key and nonce for a body, derived from the key and
+/// the salt.
+pub(crate) struct RecordKeys {
+    key: Vec<u8>,
+    nonce: [u8; 12],
+}
+
+impl RecordKeys {
+    pub(crate) fn new(ikm: &[u8], salt: &[u8]) -> Result<RecordKeys, WebPushError> {
+        let key = crypto::hkdf_sha256(salt, ikm, b"Content-Encoding: aes128gcm\0", 16)?;
+        let nonce_bytes = crypto::hkdf_sha256(salt, ikm, b"Content-Encoding: nonce\0", 12)?;
+
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&nonce_bytes);
+
+        Ok(RecordKeys { key, nonce })
+    }
+
+    /// The nonce for a record is the base nonce XORed with its sequence number.
+    fn nonce(&self, sequence: u64) -> [u8; 12] {
+        let mut nonce = self.nonce;
+
+        for (n, s) in nonce[4..].iter_mut().zip(sequence.to_be_bytes().iter()) {
+            *n ^= s;
+        }
+
+        nonce
+    }
+
+    /// Encrypts a record in place, appending the authentication tag.
+    pub(crate) fn seal(&self, sequence: u64, record: &mut Vec<u8>) -> Result<(), WebPushError> {
+        Backend::aes_128_gcm_seal(&self.key, &self.nonce(sequence), record)
+    }
+
+    /// Decrypts a record in place, returning the length of the plaintext.
+    pub(crate) fn open(&self, sequence: u64, record: &mut [u8]) -> Result<usize, WebPushError> {
+        Backend::aes_128_gcm_open(&self.key, &self.nonce(sequence), record)
+    }
+}
+
+/// Splits the content into records and encrypts them one by one.
+struct Sealer {
+    keys: RecordKeys,
+    capacity: usize,
+    sequence: u64,
+}
+
+impl Sealer {
+    fn new(key: &[u8], header: &Header) -> Result<Sealer, WebPushError> {
+        Ok(Sealer {
+            keys: RecordKeys::new(key, &header.salt)?,
+            capacity: header.record_capacity(),
+            sequence: 0,
+        })
+    }
+
+    /// Encrypts the next record, which has to fit in the record size.
+    fn seal(
+        &mut self,
+        content: &[u8],
+        padding_len: usize,
+        last: bool,
+    ) -> Result<Vec<u8>, WebPushError> {
+        debug_assert!(content.len() + padding_len <= self.capacity);
+
+        let mut record = Vec::with_capacity(content.len() + padding_len + RECORD_OVERHEAD);
+        record.extend_from_slice(content);
+        record.push(if last { 2 } else { 1 });
+        record.resize(content.len() + padding_len + 1, 0);
+
+        self.keys.seal(self.sequence, &mut record)?;
+        self.sequence += 1;
+
+        Ok(record)
+    }
+}
+
+/// Reads the records and checks their delimiters.
+struct Opener {
+    keys: RecordKeys,
+    record_size: usize,
+    sequence: u64,
+    done: bool,
+}
+
+impl Opener {
+    fn new(key: &[u8], header: &Header) -> Result<Opener, WebPushError> {
+        Ok(Opener {
+            keys: RecordKeys::new(key, &header.salt)?,
+            record_size: header.record_size as usize,
+            sequence: 0,
+            done: false,
+        })
+    }
+
+    /// Decrypts the next record, returning its content without padding.
+    /// Only the last record can be shorter than the record size.
+    fn open(&mut self, mut record: Vec<u8>) -> Result<Vec<u8>, WebPushError> {
+        if self.done || record.len() <= TAG_LEN || record.len() > self.record_size {
+            return Err(WebPushError::InvalidRecord);
+        }
+
+        let full = record.len() == self.record_size;
+        let plaintext_len = self.keys.open(self.sequence, &mut record)?;
+        record.truncate(plaintext_len);
+        self.sequence += 1;
+
+        match record.iter().rposition(|b| *b != 0) {
+            Some(delimiter) if record[delimiter] == 2 => {
+                self.done = true;
+                record.truncate(delimiter);
+                Ok(record)
+            }
+            Some(delimiter) if record[delimiter] == 1 && full => {
+                record.truncate(delimiter);
+                Ok(record)
+            }
+            Some(delimiter) if record[delimiter] == 1 => Err(WebPushError::InvalidRecord),
+            _ => Err(WebPushError::InvalidPadding),
+        }
+    }
+
+    /// Fails if the content ended before the last record.
+    fn finish(&self) -> Result<(), WebPushError> {
+        if self.done {
+            Ok(())
+        } else {
+            Err(WebPushError::InvalidRecord)
+        }
+    }
+}
+
+/// Encrypts the content, splitting it into as many records as needed.
+pub fn encrypt(key: &[u8], header: &Header, content: &[u8]) -> Result<Vec<u8>, WebPushError> {
+    encrypt_padded(key, header, content, 0)
+}
+
+/// Encrypts the content followed by `padding_len` bytes of padding. The
+/// padding fills up the last record, continuing in more records if needed.
+pub fn encrypt_padded(
+    key: &[u8],
+    header: &Header,
+    content: &[u8],
+    padding_len: usize,
+) -> Result<Vec<u8>, WebPushError> {
+    let mut sealer = Sealer::new(key, header)?;
+    let mut body = header.to_bytes();
+    let mut content = content;
+    let mut padding_len = padding_len;
+
+    loop {
+        let (record, rest) = content.split_at(content.len().min(sealer.capacity));
+        let record_padding = padding_len.min(sealer.capacity - record.len());
+
+        content = rest;
+        padding_len -= record_padding;
+
+        let last = content.is_empty() && padding_len == 0;
+        body.extend_from_slice(&sealer.seal(record, record_padding, last)?);
+
+        if last {
+            return Ok(body);
+        }
+    }
+}
+
+/// Decrypts a body starting with the header.
+pub fn decrypt(key: &[u8], body: &[u8]) -> Result<Vec<u8>, WebPushError> {
+    let (header, records) = Header::parse(body)?;
+
+    decrypt_records(key, &header, records)
+}
+
+/// Decrypts the records following an already parsed header, for when the key
+/// depends on the key id.
+pub fn decrypt_records(
+    key: &[u8],
+    header: &Header,
+    records: &[u8],
+) -> Result<Vec<u8>, WebPushError> {
+    let mut opener = Opener::new(key, header)?;
+    let mut content = Vec::with_capacity(records.len());
+
+    for record in records.chunks(header.record_size as usize) {
+        content.extend_from_slice(&opener.open(record.to_vec())?);
+    }
+
+    opener.finish()?;
+
+    Ok(content)
+}
+
+fn invalid_data(err: WebPushError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
+
+/// Encrypts everything written to it into the inner writer. The content is
+/// buffered until a record is full, so [finish](#method.finish) has to be
+/// called to write the last record.
+pub struct EceWriter<W: Write> {
+    inner: W,
+    sealer: Sealer,
+    header: Option<Vec<u8>>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EceWriter<W> {
+    pub fn new(inner: W, key: &[u8], header: &Header) -> Result<EceWriter<W>, WebPushError> {
+        let sealer = Sealer::new(key, header)?;
+
+        Ok(EceWriter {
+            inner,
+            buffer: Vec::with_capacity(sealer.capacity + 1),
+            sealer,
+            header: Some(header.to_bytes()),
+        })
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        if let Some(header) = self.header.take() {
+            self.inner.write_all(&header)?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the last record and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_header()?;
+
+        let record = self
+            .sealer
+            .seal(&self.buffer, 0, true)
+            .map_err(invalid_data)?;
+        self.inner.write_all(&record)?;
+        self.inner.flush()?;
+
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EceWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_header()?;
+
+        let capacity = self.sealer.capacity;
+        let len = buf.len().min(capacity + 1 - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..len]);
+
+        // Only with more content following a full record do we know it is
+        // not the last one.
+        if self.buffer.len() > capacity {
+            let record = self
+                .sealer
+                .seal(&self.buffer[..capacity], 0, false)
+                .map_err(invalid_data)?;
+            self.inner.write_all(&record)?;
+            self.buffer.drain(..capacity);
+        }
+
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts the content read from the inner reader.
+pub struct EceReader<R: Read> {
+    inner: R,
+    opener: Opener,
+    plaintext: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read> EceReader<R> {
+    /// Reads the header from the stream and decrypts the rest with the key.
+    pub fn new(mut inner: R, key: &[u8]) -> io::Result<EceReader<R>> {
+        let header = Header::read_from(&mut inner)?;
+
+        Self::with_header(inner, key, &header)
+    }
+
+    /// Decrypts the records with an already read header, for when the key
+    /// depends on the key id.
+    pub fn with_header(inner: R, key: &[u8], header: &Header) -> io::Result<EceReader<R>> {
+        Ok(EceReader {
+            inner,
+            opener: Opener::new(key, header).map_err(invalid_data)?,
+            plaintext: Vec::new(),
+            position: 0,
+        })
+    }
+
+    /// Reads the next full record, or what is left of the stream.
+    fn read_record(&mut self) -> io::Result<Vec<u8>> {
+        let mut record = vec![0u8; self.opener.record_size];
+        let mut len = 0;
+
+        while len < record.len() {
+            match self.inner.read(&mut record[len..]) {
+                Ok(0) => break,
+                Ok(n) => len += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        record.truncate(len);
+        Ok(record)
+    }
+}
+
+impl<R: Read> Read for EceReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plaintext.len() {
+            let record = self.read_record()?;
+
+            if record.is_empty() {
+                self.opener.finish().map_err(invalid_data)?;
+                return Ok(0);
+            }
+
+            self.plaintext = self.opener.open(record).map_err(invalid_data)?;
+            self.position = 0;
+        }
+
+        let len = buf.len().min(self.plaintext.len() - self.position);
+        buf[..len].copy_from_slice(&self.plaintext[self.position..self.position + len]);
+        self.position += len;
+
+        Ok(len)
+    }
+}
+
+#[cfg(feature = "futures")]
+mod stream {
+    use super::{Header, Opener, Sealer};
+    use crate::error::WebPushError;
+    use futures::stream::{self, Stream, StreamExt};
+
+    struct Chunks<S> {
+        inner: std::pin::Pin<Box<S>>,
+        buffer: Vec<u8>,
+        inner_done: bool,
+        done: bool,
+    }
+
+    impl<S, B, E> Chunks<S>
+    where
+        S: Stream<Item = Result<B, E>>,
+        B: AsRef<[u8]>,
+        E: Into<WebPushError>,
+    {
+        fn new(inner: S) -> Chunks<S> {
+            Chunks {
+                inner: Box::pin(inner),
+                buffer: Vec::new(),
+                inner_done: false,
+                done: false,
+            }
+        }
+
+        /// Reads from the inner stream until the buffer has more than `len`
+        /// bytes or the stream ends.
+        async fn fill(&mut self, len: usize) -> Result<(), WebPushError> {
+            while self.buffer.len() <= len && !self.inner_done {
+                match self.inner.next().await {
+                    Some(chunk) => self
+                        .buffer
+                        .extend_from_slice(chunk.map_err(Into::into)?.as_ref()),
+                    None => self.inner_done = true,
+                }
+            }
+
+            Ok(())
+        }
+    }
+
+    /// Encrypts a stream of content, yielding the header and then each record
+    /// as soon as it is full.
+    pub fn encrypt_stream<S, B, E>(
+        key: &[u8],
+        header: &Header,
+        content: S,
+    ) -> Result<impl Stream<Item = Result<Vec<u8>, WebPushError>>, WebPushError>
+    where
+        S: Stream<Item = Result<B, E>>,
+        B: AsRef<[u8]>,
+        E: Into<WebPushError>,
+    {
+        let sealer = Sealer::new(key, header)?;
+        let first = stream::once(futures::future::ready(Ok(header.to_bytes())));
+
+        let records = stream::unfold(
+            (Chunks::new(content), sealer),
+            |(mut chunks, mut sealer)| async move {
+                if chunks.done {
+                    return None;
+                }
+
+                let capacity = sealer.capacity;
+
+                let record = match chunks.fill(capacity).await {
+                    Ok(()) if chunks.buffer.len() > capacity => {
+                        let record = sealer.seal(&chunks.buffer[..capacity], 0, false);
+                        chunks.buffer.drain(..capacity);
+                        record
+                    }
+                    Ok(()) => {
+                        chunks.done = true;
+                        sealer.seal(&chunks.buffer, 0, true)
+                    }
+                    Err(e) => {
+                        chunks.done = true;
+                        Err(e)
+                    }
+                };
+
+                Some((record, (chunks, sealer)))
+            },
+        );
+
+        Ok(first.chain(records))
+    }
+
+    /// Decrypts a stream starting with the header, yielding the content of
+    /// each record.
+    pub fn decrypt_stream<S, B, E>(
+        key: &[u8],
+        content: S,
+    ) -> impl Stream<Item = Result<Vec<u8>, WebPushError>>
+    where
+        S: Stream<Item = Result<B, E>>,
+        B: AsRef<[u8]>,
+        E: Into<WebPushError>,
+    {
+        let key = key.to_vec();
+
+        stream::unfold(
+            (Chunks::new(content), None::<Opener>),
+            move |(mut chunks, mut opener)| {
+                let key = key.clone();
+
+                async move {
+                    if chunks.done {
+                        return None;
+                    }
+
+                    let result = async {
+                        if opener.is_none() {
+                            chunks.fill(20).await?;
+                            let header_len = 21 + *chunks.buffer.get(20).unwrap_or(&0) as usize;
+                            chunks.fill(header_len - 1).await?;
+
+                            let (header, _) = Header::parse(&chunks.buffer)?;
+                            chunks.buffer.drain(..header.encoded_len());
+                            opener = Some(Opener::new(&key, &header)?);
+                        }
+
+                        let opener = opener.as_mut().unwrap();
+                        chunks.fill(opener.record_size - 1).await?;
+
+                        if chunks.buffer.is_empty() {
+                            opener.finish()?;
+                            return Ok(None);
+                        }
+
+                        let len = chunks.buffer.len().min(opener.record_size);
+                        let record = chunks.buffer.drain(..len).collect();
+
+                        opener.open(record).map(Some)
+                    }
+                    .await;
+
+                    match result {
+                        Ok(Some(content)) => Some((Ok(content), (chunks, opener))),
+                        Ok(None) => None,
+                        Err(e) => {
+                            chunks.done = true;
+                            Some((Err(e), (chunks, opener)))
+                        }
+                    }
+                }
+            },
+        )
+    }
+}
+
+#[cfg(feature = "futures")]
+pub use self::stream::{decrypt_stream, encrypt_stream};
+
+#[cfg(test)]
+mod tests {
+    use crate::ece::{self, EceReader, EceWriter, Header, RecordKeys};
+    use crate::error::WebPushError;
+    use base64::{self, URL_SAFE, URL_SAFE_NO_PAD};
+    use std::convert::TryInto;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_encrypt_rfc8188_single_record() {
+        // RFC 8188, 3.1. Encryption of a Response
+        let key = base64::decode_config("yqdlZ-tYemfogSmv7Ws5PQ", URL_SAFE).unwrap();
+        let salt = base64::decode_config("I1BsxtFttlv3u_Oo94xnmw", URL_SAFE).unwrap();
+        let header = Header::new(salt[..].try_into().unwrap(), 4096, b"").unwrap();
+
+        let body = ece::encrypt(&key, &header, b"I am the walrus").unwrap();
+
+        assert_eq!(
+            "I1BsxtFttlv3u_Oo94xnmwAAEAAA-NAVub2qFgBEuQKRapoZu-IxkIva3MEB1PD-ly8Thjg",
+            base64::encode_config(&body, URL_SAFE_NO_PAD)
+        );
+        assert_eq!(
+            b"I am the walrus".to_vec(),
+            ece::decrypt(&key, &body).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_rfc8188_multiple_records() {
+        // RFC 8188, 3.2. Encryption with Multiple Records, the first record
+        // has one byte of padding.
+        let key = base64::decode_config("BO3ZVPxUlnLORbVGMpbT1Q", URL_SAFE).unwrap();
+        let body = base64::decode_config("uNCkWiNYzKTnBN9ji3-qWAAAABkCYTHOG8chz_gnvgOqdGYovxyjuqRyJFjEDyoF1Fvkj6hQPdPHI51OEUKEpgz3SsLWIqS_uA",
+                                         URL_SAFE).unwrap();
+
+        let (header, _) = Header::parse(&body).unwrap();
+        assert_eq!(25, header.record_size);
+        assert_eq!(b"a1".to_vec(), header.key_id);
+
+        let keys = RecordKeys::new(&key, &header.salt).unwrap();
+        let mut first = b"I am th\x01\x00".to_vec();
+        keys.seal(0, &mut first).unwrap();
+        let mut second = b"e walrus\x02".to_vec();
+        keys.seal(1, &mut second).unwrap();
+
+        let mut expected = header.to_bytes();
+        expected.extend_from_slice(&first);
+        expected.extend_from_slice(&second);
+        assert_eq!(expected, body);
+
+        assert_eq!(
+            b"I am the walrus".to_vec(),
+            ece::decrypt(&key, &body).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encrypt_padded_records() {
+        let key = [7u8; 16];
+        let header = Header::new([1u8; 16], 25, b"a1").unwrap();
+
+        // eight bytes of content fit in a record of 25
+        let body = ece::encrypt_padded(&key, &header, b"I am the walrus", 20).unwrap();
+        assert_eq!(header.encoded_len() + 4 * 25 + 20, body.len());
+        assert_eq!(
+            b"I am the walrus".to_vec(),
+            ece::decrypt(&key, &body).unwrap()
+        );
+
+        let body = ece::encrypt(&key, &header, b"").unwrap();
+        assert_eq!(header.encoded_len() + 17, body.len());
+        assert_eq!(Vec::<u8>::new(), ece::decrypt(&key, &body).unwrap());
+
+        let body = ece::encrypt(&key, &header, b"walrus!!").unwrap();
+        assert_eq!(header.encoded_len() + 25, body.len());
+    }
+
+    #[test]
+    fn test_decrypt_invalid_records() {
+        let key = [7u8; 16];
+        let header = Header::new([1u8; 16], 25, b"").unwrap();
+        let body = ece::encrypt(&key, &header, b"I am the walrus").unwrap();
+
+        // the last record missing
+        assert_eq!(
+            Err(WebPushError::InvalidRecord),
+            ece::decrypt(&key, &body[..header.encoded_len() + 25])
+        );
+
+        // a record after the last one, which is full
+        let body = ece::encrypt(&key, &header, b"I am the walrus!").unwrap();
+        let mut extra = body.clone();
+        extra.extend_from_slice(&body[header.encoded_len()..header.encoded_len() + 25]);
+        assert_eq!(Err(WebPushError::InvalidRecord), ece::decrypt(&key, &extra));
+
+        assert_eq!(
+            Err(WebPushError::InvalidAuthenticationTag),
+            ece::decrypt(&[8u8; 16], &body)
+        );
+
+        assert_eq!(
+            Err(WebPushError::InvalidRecord),
+            ece::decrypt(&key, &body[..20])
+        );
+        assert_eq!(
+            Err(WebPushError::InvalidRecord),
+            Header::new([1u8; 16], 17, b"")
+        );
+    }
+
+    #[test]
+    fn test_writer_and_reader() {
+        let key = [7u8; 16];
+        let header = Header::new([1u8; 16], 100, b"key").unwrap();
+        let content: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+
+        let mut writer = EceWriter::new(Vec::new(), &key, &header).unwrap();
+        for chunk in content.chunks(77) {
+            writer.write_all(chunk).unwrap();
+        }
+        let body = writer.finish().unwrap();
+
+        assert_eq!(ece::encrypt(&key, &header, &content).unwrap(), body);
+
+        let mut reader = EceReader::new(&body[..], &key).unwrap();
+        let mut decrypted = Vec::new();
+        reader.read_to_end(&mut decrypted).unwrap();
+
+        assert_eq!(content, decrypted);
+
+        let mut reader = EceReader::new(&body[..body.len() - 1], &key).unwrap();
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[cfg(feature = "futures")]
+    #[tokio::test]
+    async fn test_streams() {
+        use futures::stream::{self, StreamExt, TryStreamExt};
+
+        let key = [7u8; 16];
+        let header = Header::new([1u8; 16], 100, b"key").unwrap();
+        let content: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+
+        let chunks = content
+            .chunks(77)
+            .map(|chunk| Ok::<_, WebPushError>(chunk.to_vec()))
+            .collect::<Vec<_>>();
+
+        let records: Vec<Vec<u8>> = ece::encrypt_stream(&key, &header, stream::iter(chunks))
+            .unwrap()
+            .try_collect()
+            .await
+            .unwrap();
+        let body = records.concat();
+
+        assert_eq!(ece::encrypt(&key, &header, &content).unwrap(), body);
+
+        let chunks = body
+            .chunks(33)
+            .map(|chunk| Ok::<_, WebPushError>(chunk.to_vec()))
+            .collect::<Vec<_>>();
+
+        let decrypted: Vec<Vec<u8>> = ece::decrypt_stream(&key, stream::iter(chunks))
+            .try_collect()
+            .await
+            .unwrap();
+
+        assert_eq!(content, decrypted.concat());
+
+        let truncated = vec![Ok::<_, WebPushError>(body[..body.len() - 1].to_vec())];
+        let results: Vec<_> = ece::decrypt_stream(&key, stream::iter(truncated))
+            .collect()
+            .await;
+
+        assert!(results.last().unwrap().is_err());
+    }
+}
@@ -1,8 +1,9 @@
 use base64::DecodeError;
-use ring::error;
 use serde_json::error::Error as JsonError;
 use std::string::FromUtf8Error;
-use std::time::{Duration, SystemTime};
+use std::time::Duration;
+#[cfg(any(feature = "ureq", feature = "hyper"))]
+use std::time::SystemTime;
 use std::{convert::From, error::Error, fmt};
 
 #[derive(PartialEq, Debug)]
@@ -15,6 +16,9 @@ pub enum WebPushError {
     BadRequest(Option<String>),
     /// Contains an optional `Duration`, until the user can retry the request
     ServerError(Option<Duration>),
+    /// Too many messages were sent, contains an optional `Duration` until
+    /// the user can retry the request
+    TooManyRequests(Option<Duration>),
     /// The feature is not implemented yet
     NotImplemented,
     /// The provided URI is invalid
@@ -23,8 +27,9 @@ pub enum WebPushError {
     EndpointNotValid,
     /// The URL specified is invalid and should not be used again
     EndpointNotFound,
-    /// Maximum allowed payload size is 3800 characters
-    PayloadTooLarge,
+    /// The payload is too large for the push service, contains the sizes if
+    /// the payload was checked before sending
+    PayloadTooLarge(Option<PayloadSize>),
     /// Could not initialize a TLS connection
     TlsError,
     /// The TTL value provided was not valid or was not provided
@@ -35,9 +40,30 @@ pub enum WebPushError {
     InvalidCryptoKeys,
     /// Corrupted response data
     InvalidResponse,
+    /// The encrypted content could not be authenticated, it was either
+    /// tampered with or encrypted with different keys
+    InvalidAuthenticationTag,
+    /// The decrypted content has malformed padding
+    InvalidPadding,
+    /// The encrypted content has a malformed header or is missing records
+    InvalidRecord,
+    /// The push service did not answer in time
+    Timeout,
+    /// The topic is longer than 32 characters or has characters outside of
+    /// the URL safe base64 alphabet
+    InvalidTopic,
     Other(String),
 }
 
+/// The size of a payload that did not fit and the largest size that would have.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct PayloadSize {
+    /// The length of the content in bytes
+    pub actual: usize,
+    /// The maximum length of the content in bytes
+    pub allowed: usize,
+}
+
 // impl fmt::Display for WebPushError {
 //     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 //         <Self as fmt::Debug>::fmt(self, f)
@@ -56,7 +82,12 @@ pub struct ErrorInfo {
 impl From<hyper::Error> for WebPushError {
     fn from(err: hyper::Error) -> Self {
         debug!("{}", err);
-        Self::Unspecified
+
+        if is_timeout(&err) {
+            Self::Timeout
+        } else {
+            Self::Unspecified
+        }
     }
 }
 
@@ -78,11 +109,30 @@ impl From<ureq::Error> for WebPushError {
                     .and_then(retry_after_from_str);
                 Self::from_error_response(status, retry_after, || response.into_json::<ErrorInfo>())
             }
+            err if is_timeout(&err) => Self::Timeout,
             err => Self::Other(err.to_string()),
         }
     }
 }
 
+/// Is any of the causes of the error an I/O timeout.
+#[cfg(any(feature = "ureq", feature = "hyper"))]
+fn is_timeout(err: &(dyn Error + 'static)) -> bool {
+    let mut source = Some(err);
+
+    while let Some(err) = source {
+        if let Some(io_error) = err.downcast_ref::<std::io::Error>() {
+            if let std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock = io_error.kind() {
+                return true;
+            }
+        }
+
+        source = err.source();
+    }
+
+    false
+}
+
 impl WebPushError {
     pub fn from_error_response<ReadJsonError>(
         status: u16,
@@ -95,7 +145,8 @@ impl WebPushError {
             401 => Self::Unauthorized,
             410 => Self::EndpointNotValid,
             404 => Self::EndpointNotFound,
-            413 => Self::PayloadTooLarge,
+            413 => Self::PayloadTooLarge(None),
+            429 => Self::TooManyRequests(retry_after),
 
             400 => match read_body_as_error_info_json() {
                 Ok(error_info) => Self::BadRequest(Some(error_info.error)),
@@ -119,12 +170,6 @@ impl From<FromUtf8Error> for WebPushError {
     }
 }
 
-impl From<error::Unspecified> for WebPushError {
-    fn from(_: error::Unspecified) -> Self {
-        Self::Unspecified
-    }
-}
-
 impl From<DecodeError> for WebPushError {
     fn from(_: DecodeError) -> Self {
         Self::InvalidCryptoKeys
@@ -138,16 +183,22 @@ impl WebPushError {
             Self::Unauthorized => "unauthorized",
             Self::BadRequest(_) => "bad_request",
             Self::ServerError(_) => "server_error",
+            Self::TooManyRequests(_) => "too_many_requests",
             Self::NotImplemented => "not_implemented",
             Self::InvalidUri => "invalid_uri",
             Self::EndpointNotValid => "endpoint_not_valid",
             Self::EndpointNotFound => "endpoint_not_found",
-            Self::PayloadTooLarge => "payload_too_large",
+            Self::PayloadTooLarge(_) => "payload_too_large",
             Self::TlsError => "tls_error",
             Self::InvalidTtl => "invalid_ttl",
             Self::InvalidResponse => "invalid_response",
             Self::MissingCryptoKeys => "missing_crypto_keys",
             Self::InvalidCryptoKeys => "invalid_crypto_keys",
+            Self::InvalidAuthenticationTag => "invalid_authentication_tag",
+            Self::InvalidPadding => "invalid_padding",
+            Self::InvalidRecord => "invalid_record",
+            Self::Timeout => "timeout",
+            Self::InvalidTopic => "invalid_topic",
             Self::Other(_) => "other",
         }
     }
@@ -162,7 +213,8 @@ impl Error for WebPushError {
             Self::ServerError(_) => {
                 "Server was unable to process the request, please try again later"
             }
-            Self::PayloadTooLarge => "Maximum allowed payload size is 3070 characters",
+            Self::TooManyRequests(_) => "Too many messages were sent, please try again later",
+            Self::PayloadTooLarge(_) => "The payload is too large for the push service",
             Self::InvalidUri => "The provided URI is invalid",
             Self::NotImplemented => "The feature is not implemented yet",
             Self::EndpointNotValid => {
@@ -174,6 +226,11 @@ impl Error for WebPushError {
             Self::InvalidResponse => "The response data couldn't be parses",
             Self::MissingCryptoKeys => "The request is missing cryptographic keys",
             Self::InvalidCryptoKeys => "The request is having invalid cryptographic keys",
+            Self::InvalidAuthenticationTag => "The encrypted content could not be authenticated",
+            Self::InvalidPadding => "The decrypted content has invalid padding",
+            Self::InvalidRecord => "The encrypted content has an invalid header or records",
+            Self::Timeout => "The push service did not answer in time",
+            Self::InvalidTopic => "The topic is not 1 to 32 characters of URL safe base64",
             Self::Other(_) => "An unknown error when connecting the notification service",
         }
     }
@@ -185,10 +242,21 @@ impl Error for WebPushError {
 
 impl fmt::Display for WebPushError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "WebPushError: {}", self)
+        #[allow(deprecated)]
+        let description = self.description();
+
+        match self {
+            Self::PayloadTooLarge(Some(size)) => write!(
+                f,
+                "WebPushError: {}, {} bytes of at most {}",
+                description, size.actual, size.allowed
+            ),
+            _ => write!(f, "WebPushError: {}", description),
+        }
     }
 }
 
+#[cfg(any(feature = "ureq", feature = "hyper"))]
 pub fn retry_after_from_str(header_value: &str) -> Option<Duration> {
     if let Ok(seconds) = header_value.parse::<u64>() {
         Some(Duration::from_secs(seconds))
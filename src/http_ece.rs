@@ -1,168 +1,418 @@
-use crate::error::WebPushError;
+use crate::crypto::{self, Backend, CryptoBackend};
+use crate::ece::{self, RecordKeys};
+use crate::error::{PayloadSize, WebPushError};
 use crate::message::WebPushPayload;
-use crate::vapid::VapidSignature;
 use base64::{self, URL_SAFE_NO_PAD};
-use ring::rand::SecureRandom;
-use ring::{
-    aead::{self, BoundKey},
-    agreement, hkdf, rand,
-};
+use std::fmt;
+use std::sync::Arc;
 
+/// The default limit for the size of the encrypted body. Push services have
+/// to accept at least 4096 bytes, but this is the largest that has worked with
+/// both Google's and Mozilla's push services.
+pub const DEFAULT_BODY_LIMIT: usize = 3070;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ContentEncoding {
     AesGcm,
     Aes128Gcm,
 }
 
-pub struct HttpEce<'a> {
-    peer_public_key: &'a [u8],
-    peer_secret: &'a [u8],
-    encoding: ContentEncoding,
-    rng: rand::SystemRandom,
-    vapid_signature: Option<VapidSignature>,
+impl ContentEncoding {
+    /// The bytes added to the content by encryption, not counting padding.
+    fn overhead(&self) -> usize {
+        match self {
+            // padding length and the authentication tag
+            Self::AesGcm => 2 + 16,
+            // header with the public key as key id, delimiter and the tag
+            Self::Aes128Gcm => 21 + 65 + 1 + 16,
+        }
+    }
+}
+
+/// The size limits for the content of a push message with a given encoding,
+/// padding policy and the largest body the push service accepts.
+///
+/// ```
+/// # use web_push::*;
+/// let budget = PayloadBudget::new(&ContentEncoding::Aes128Gcm, &PaddingPolicy::None, 4096);
+///
+/// assert_eq!(3993, budget.max_content_len());
+/// assert_eq!(Some(123), budget.encrypted_len(20));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PayloadBudget {
+    overhead: usize,
+    max_content_len: usize,
+    padding: PaddingPolicy,
 }
 
-#[derive(Debug, PartialEq)]
-struct EceKey<T: core::fmt::Debug + PartialEq>(T);
+impl PayloadBudget {
+    pub fn new(
+        encoding: &ContentEncoding,
+        padding: &PaddingPolicy,
+        body_limit: usize,
+    ) -> PayloadBudget {
+        PayloadBudget {
+            overhead: encoding.overhead(),
+            max_content_len: body_limit.saturating_sub(encoding.overhead()),
+            padding: padding.clone(),
+        }
+    }
+
+    /// The largest content that can be sent. Padding is never added past
+    /// this size, so it does not depend on the padding policy.
+    pub fn max_content_len(&self) -> usize {
+        self.max_content_len
+    }
 
-impl hkdf::KeyType for EceKey<usize> {
-    fn len(&self) -> usize {
-        self.0
+    /// The size of the encrypted body when sending the largest content.
+    pub fn max_body_len(&self) -> usize {
+        self.overhead + self.max_content_len
+    }
+
+    /// The number of padding bytes that are added to content of the given
+    /// length.
+    pub fn padding_len(&self, content_len: usize) -> usize {
+        self.padding.padding_len(content_len, self.max_content_len)
+    }
+
+    /// The size of the encrypted body for content of the given length, or
+    /// `None` if the content is too large.
+    pub fn encrypted_len(&self, content_len: usize) -> Option<usize> {
+        if content_len > self.max_content_len {
+            None
+        } else {
+            Some(self.overhead + content_len + self.padding_len(content_len))
+        }
     }
-}
 
-impl From<hkdf::Okm<'_, EceKey<usize>>> for EceKey<Vec<u8>> {
-    fn from(okm: hkdf::Okm<EceKey<usize>>) -> Self {
-        let mut r = vec![0u8; okm.len().0];
-        okm.fill(&mut r).unwrap();
-        EceKey(r)
+    /// Fails with `PayloadTooLarge` if content of the given length does not fit.
+    pub fn check(&self, content_len: usize) -> Result<(), WebPushError> {
+        if content_len > self.max_content_len {
+            Err(WebPushError::PayloadTooLarge(Some(PayloadSize {
+                actual: content_len,
+                allowed: self.max_content_len,
+            })))
+        } else {
+            Ok(())
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Default)]
-struct EceNonce {
-    used: bool,
-    nonce: Vec<u8>,
+/// How much padding to add to the content before encryption. Padding hides
+/// the length of the message from anyone seeing the encrypted request, but
+/// makes the request bigger.
+///
+/// The sizes are of the content plus padding, and never go over the largest
+/// content the push service accepts, see [PayloadBudget](struct.PayloadBudget.html).
+#[derive(Clone, Default)]
+pub enum PaddingPolicy {
+    /// No padding, the encrypted message is as small as possible.
+    None,
+    /// Pads the content to the next power of two.
+    PowerOfTwo,
+    /// Pads the content to the smallest bucket it fits in. Content larger than
+    /// every bucket is not padded.
+    Buckets(Vec<usize>),
+    /// Pads every message to the largest content size. The default.
+    #[default]
+    Max,
+    /// Computes the padded size from the content length.
+    Custom(Arc<dyn Fn(usize) -> usize + Send + Sync>),
 }
 
-impl EceNonce {
-    fn fill(&mut self, nonce: Vec<u8>) {
-        self.nonce = nonce;
-        self.used = false;
+impl PaddingPolicy {
+    /// The number of padding bytes to add to content of the given length, when
+    /// the content with padding can be at most `max_content_len`.
+    pub fn padding_len(&self, content_len: usize, max_content_len: usize) -> usize {
+        let padded_len = match self {
+            Self::None => content_len,
+            Self::PowerOfTwo => content_len.next_power_of_two(),
+            Self::Buckets(buckets) => buckets
+                .iter()
+                .filter(|bucket| **bucket >= content_len)
+                .min()
+                .copied()
+                .unwrap_or(content_len),
+            Self::Max => max_content_len,
+            Self::Custom(padded_len) => padded_len(content_len),
+        };
+
+        padded_len.min(max_content_len).saturating_sub(content_len)
     }
 }
 
-impl aead::NonceSequence for EceNonce {
-    fn advance(&mut self) -> Result<aead::Nonce, ring::error::Unspecified> {
-        if self.used {
-            return Err(ring::error::Unspecified);
+impl fmt::Debug for PaddingPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "None"),
+            Self::PowerOfTwo => write!(f, "PowerOfTwo"),
+            Self::Buckets(buckets) => f.debug_tuple("Buckets").field(buckets).finish(),
+            Self::Max => write!(f, "Max"),
+            Self::Custom(_) => write!(f, "Custom"),
         }
+    }
+}
 
-        let mut nonce = [0u8; 12];
+pub struct HttpEce<'a> {
+    peer_public_key: &'a [u8],
+    peer_secret: &'a [u8],
+    encoding: ContentEncoding,
+    padding: PaddingPolicy,
+    body_limit: usize,
+    key_source: Box<dyn EceKeySource + 'a>,
+}
 
-        for (i, n) in self.nonce.iter().enumerate() {
-            if i >= 12 {
-                return Err(ring::error::Unspecified);
-            }
+/// The key material which has to be unique for every encrypted message: the
+/// application server's ephemeral private key and the salt.
+///
+/// By default the keys come from the system's secure random generator. For
+/// reproducible output in tests a [FixedKeySource](struct.FixedKeySource.html)
+/// can be set with [HttpEce::set_key_source](struct.HttpEce.html#method.set_key_source).
+pub trait EceKeySource {
+    /// A P-256 private key as a 32 byte big-endian scalar.
+    fn ephemeral_private_key(&self) -> Result<Vec<u8>, WebPushError>;
+
+    /// A 16 byte salt.
+    fn salt(&self) -> Result<[u8; 16], WebPushError>;
+
+    /// The ECDH key agreement of the ephemeral key with the peer, returning
+    /// the ephemeral public key and the shared secret.
+    fn agree(&self, peer_public_key: &[u8]) -> Result<(Vec<u8>, Vec<u8>), WebPushError> {
+        let private_key = self.ephemeral_private_key()?;
+
+        Ok((
+            Backend::public_key(&private_key)?,
+            Backend::ecdh(&private_key, peer_public_key)?,
+        ))
+    }
+}
+
+/// Generates new keys for every message from the system's secure random
+/// generator.
+#[derive(Default)]
+pub struct SystemKeySource;
+
+impl SystemKeySource {
+    pub fn new() -> SystemKeySource {
+        SystemKeySource
+    }
+}
+
+impl EceKeySource for SystemKeySource {
+    fn ephemeral_private_key(&self) -> Result<Vec<u8>, WebPushError> {
+        Ok(Backend::generate_key_pair()?.0)
+    }
+
+    fn salt(&self) -> Result<[u8; 16], WebPushError> {
+        let mut salt = [0u8; 16];
+        Backend::fill_random(&mut salt)?;
+
+        Ok(salt)
+    }
+
+    fn agree(&self, peer_public_key: &[u8]) -> Result<(Vec<u8>, Vec<u8>), WebPushError> {
+        Backend::ephemeral_ecdh(peer_public_key)
+    }
+}
+
+/// Always returns the same keys. Only to be used for testing, reusing the
+/// keys for more than one message breaks the encryption.
+pub struct FixedKeySource {
+    private_key: Vec<u8>,
+    salt: [u8; 16],
+}
 
-            nonce[i] = *n;
+impl FixedKeySource {
+    pub fn new(private_key: &[u8], salt: [u8; 16]) -> FixedKeySource {
+        FixedKeySource {
+            private_key: private_key.to_vec(),
+            salt,
         }
+    }
+}
 
-        self.used = true;
+impl EceKeySource for FixedKeySource {
+    fn ephemeral_private_key(&self) -> Result<Vec<u8>, WebPushError> {
+        Ok(self.private_key.clone())
+    }
 
-        Ok(aead::Nonce::assume_unique_for_key(nonce))
+    fn salt(&self) -> Result<[u8; 16], WebPushError> {
+        Ok(self.salt)
     }
 }
 
+/// The record size written to the aes128gcm header. A web push message is
+/// always a single record, so this only has to be larger than the body.
+const AES128GCM_RECORD_SIZE: usize = 4096;
+
 impl<'a> HttpEce<'a> {
-    /// Create a new encryptor. The content encoding has preliminary support for
-    /// Aes128Gcm, which is the 8th draft of the Encrypted Content-Encoding, but
-    /// currently using it will return an error when trying to encrypt. There is
-    /// no real support yet for the encoding in web browsers.
+    /// Create a new encryptor. Supports both the legacy aesgcm encoding
+    /// (draft 3 of the Encrypted Content-Encoding) and aes128gcm as specified
+    /// in RFC 8188 and RFC 8291.
     ///
     /// `peer_public_key` is the `p256dh` and `peer_secret` the `auth` from
-    /// browser subscription info.
+    /// browser subscription info. The VAPID signature is not part of the
+    /// encryption, it is added by the
+    /// [WebPushMessageBuilder](struct.WebPushMessageBuilder.html).
     pub fn new(
         encoding: ContentEncoding,
         peer_public_key: &'a [u8],
         peer_secret: &'a [u8],
-        vapid_signature: Option<VapidSignature>,
     ) -> HttpEce<'a> {
         HttpEce {
-            rng: rand::SystemRandom::new(),
+            padding: PaddingPolicy::default(),
+            body_limit: DEFAULT_BODY_LIMIT,
+            key_source: Box::new(SystemKeySource::new()),
             peer_public_key,
             peer_secret,
             encoding,
-            vapid_signature,
         }
     }
 
-    /// Encrypts a payload. The maximum length for the payload is 3800
-    /// characters, which is the largest that works with Google's and Mozilla's
-    /// push servers.
+    /// Sets how much padding is added to the content, by default it is padded
+    /// to the maximum payload size.
+    pub fn set_padding_policy(&mut self, padding: PaddingPolicy) {
+        self.padding = padding;
+    }
+
+    /// Sets the largest encrypted body the push service accepts, by default
+    /// [DEFAULT_BODY_LIMIT](constant.DEFAULT_BODY_LIMIT.html).
+    pub fn set_body_limit(&mut self, body_limit: usize) {
+        self.body_limit = body_limit;
+    }
+
+    /// The size limits with the current encoding, padding and body limit.
+    pub fn budget(&self) -> PayloadBudget {
+        PayloadBudget::new(&self.encoding, &self.padding, self.body_limit)
+    }
+
+    /// Replaces the source of the ephemeral private key and salt.
+    pub fn set_key_source<S>(&mut self, key_source: S)
+    where
+        S: EceKeySource + 'a,
+    {
+        self.key_source = Box::new(key_source);
+    }
+
+    /// Encrypts a payload. The maximum length for the payload depends on the
+    /// encoding and the body limit, with the default limit it is 3052 bytes
+    /// for aesgcm and 2967 bytes for aes128gcm.
     pub fn encrypt(&self, content: &'a [u8]) -> Result<WebPushPayload, WebPushError> {
-        if content.len() > 3052 {
-            return Err(WebPushError::PayloadTooLarge);
-        }
+        let budget = self.budget();
+        budget.check(content.len())?;
 
-        let private_key =
-            agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &self.rng)?;
-        let public_key = private_key.compute_public_key()?;
-        let mut salt_bytes = [0u8; 16];
-
-        self.rng.fill(&mut salt_bytes)?;
-        let peer_public_key =
-            agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, self.peer_public_key);
-
-        agreement::agree_ephemeral(
-            private_key,
-            &peer_public_key,
-            WebPushError::Unspecified,
-            |shared_secret| match self.encoding {
-                ContentEncoding::AesGcm => {
-                    let mut payload = vec![0; 3054];
-                    front_pad(content, &mut payload);
-
-                    self.aes_gcm(
-                        shared_secret,
-                        public_key.as_ref(),
-                        &salt_bytes,
-                        &mut payload,
-                    )?;
-
-                    Ok(WebPushPayload {
-                        content: payload.to_vec(),
-                        crypto_headers: self.generate_headers(public_key.as_ref(), &salt_bytes),
-                        content_encoding: "aesgcm",
-                    })
-                }
-                ContentEncoding::Aes128Gcm => Err(WebPushError::NotImplemented),
-            },
-        )
+        self.encrypt_padded(content, budget.padding_len(content.len()))
     }
 
+    /// Encrypts a payload followed by `padding_len` bytes of padding.
+    fn encrypt_padded(
+        &self,
+        content: &[u8],
+        padding_len: usize,
+    ) -> Result<WebPushPayload, WebPushError> {
+        let (public_key, shared_secret) = self.key_source.agree(self.peer_public_key)?;
+        let salt_bytes = self.key_source.salt()?;
+
+        match self.encoding {
+            ContentEncoding::AesGcm => {
+                let mut payload = vec![0; content.len() + padding_len + 2];
+                front_pad(content, &mut payload);
+
+                self.aes_gcm(&shared_secret, &public_key, &salt_bytes, &mut payload)?;
+
+                Ok(WebPushPayload {
+                    content: payload,
+                    crypto_headers: self.generate_headers(&public_key, &salt_bytes),
+                    content_encoding: "aesgcm",
+                })
+            }
+            ContentEncoding::Aes128Gcm => {
+                let ikm = self.aes_128_gcm_ikm(&shared_secret, &public_key)?;
+
+                // Web push messages are a single record, grow it if the push
+                // service takes more than the usual maximum.
+                let record_size = AES128GCM_RECORD_SIZE.max(content.len() + padding_len + 17);
+                let header = ece::Header::new(salt_bytes, record_size as u32, &public_key)?;
+
+                Ok(WebPushPayload {
+                    content: ece::encrypt_padded(&ikm, &header, content, padding_len)?,
+                    crypto_headers: self.generate_headers(&public_key, &salt_bytes),
+                    content_encoding: "aes128gcm",
+                })
+            }
+        }
+    }
+
+    /// The headers to send along the encrypted content. With aes128gcm the key
+    /// and salt are part of the body, so no headers are needed.
     pub fn generate_headers(
         &self,
         public_key: &'a [u8],
         salt: &'a [u8],
     ) -> Vec<(&'static str, String)> {
-        let mut crypto_headers = Vec::new();
+        match self.encoding {
+            ContentEncoding::AesGcm => vec![
+                (
+                    "Crypto-Key",
+                    format!("dh={}", base64::encode_config(public_key, URL_SAFE_NO_PAD)),
+                ),
+                (
+                    "Encryption",
+                    format!("salt={}", base64::encode_config(&salt, URL_SAFE_NO_PAD)),
+                ),
+            ],
+            ContentEncoding::Aes128Gcm => Vec::new(),
+        }
+    }
 
-        let mut crypto_key = format!("dh={}", base64::encode_config(public_key, URL_SAFE_NO_PAD));
+    /// Decrypts a payload on the receiving side. The encryptor has to be
+    /// created with the user agent's own `p256dh` and `auth`, and
+    /// `private_key` is the raw 32 byte private key matching the `p256dh`.
+    ///
+    /// For aesgcm the salt and the sender's public key are read from the
+    /// `Encryption` and `Crypto-Key` headers, for aes128gcm from the header
+    /// block in the content.
+    pub fn decrypt(
+        &self,
+        private_key: &[u8],
+        payload: &WebPushPayload,
+    ) -> Result<Vec<u8>, WebPushError> {
+        if Backend::public_key(private_key)? != self.peer_public_key {
+            return Err(WebPushError::InvalidCryptoKeys);
+        }
 
-        if let Some(ref signature) = self.vapid_signature {
-            crypto_key = format!("{}; p256ecdsa={}", crypto_key, signature.auth_k);
+        match self.encoding {
+            ContentEncoding::AesGcm => {
+                let dh = header_param(&payload.crypto_headers, "Crypto-Key", "dh")
+                    .ok_or(WebPushError::MissingCryptoKeys)?;
+                let salt = header_param(&payload.crypto_headers, "Encryption", "salt")
+                    .ok_or(WebPushError::MissingCryptoKeys)?;
 
-            let sig_s: String = signature.into();
-            crypto_headers.push(("Authorization", sig_s));
-        };
+                let as_public_key = base64::decode_config(dh, URL_SAFE_NO_PAD)?;
+                let salt_bytes = base64::decode_config(salt, URL_SAFE_NO_PAD)?;
 
-        crypto_headers.push(("Crypto-Key", crypto_key));
-        crypto_headers.push((
-            "Encryption",
-            format!("salt={}", base64::encode_config(&salt, URL_SAFE_NO_PAD)),
-        ));
+                let shared_secret = Backend::ecdh(private_key, &as_public_key)?;
+                let (content_encryption_key, nonce) =
+                    self.aes_gcm_keys(&shared_secret, &as_public_key, &salt_bytes)?;
+
+                let mut content = payload.content.clone();
+                let plaintext_len =
+                    Backend::aes_128_gcm_open(&content_encryption_key, &nonce, &mut content)?;
+                content.truncate(plaintext_len);
+                front_unpad(&mut content)?;
+
+                Ok(content)
+            }
+            ContentEncoding::Aes128Gcm => {
+                let (header, records) = ece::Header::parse(&payload.content)?;
 
-        crypto_headers
+                let shared_secret = Backend::ecdh(private_key, &header.key_id)?;
+                let ikm = self.aes_128_gcm_ikm(&shared_secret, &header.key_id)?;
+
+                ece::decrypt_records(&ikm, &header, records)
+            }
+        }
     }
 
     /// The aesgcm encrypted content-encoding, draft 3.
@@ -173,6 +423,34 @@ impl<'a> HttpEce<'a> {
         salt_bytes: &'a [u8],
         payload: &'a mut Vec<u8>,
     ) -> Result<(), WebPushError> {
+        let (content_encryption_key, nonce) =
+            self.aes_gcm_keys(shared_secret, as_public_key, salt_bytes)?;
+
+        Backend::aes_128_gcm_seal(&content_encryption_key, &nonce, payload)
+    }
+
+    /// The aes128gcm encrypted content-encoding, RFC 8188, using the key
+    /// derivation for web push from RFC 8291. The payload must already contain
+    /// the padding delimiter.
+    pub fn aes_128_gcm(
+        &self,
+        shared_secret: &'a [u8],
+        as_public_key: &'a [u8],
+        salt_bytes: &'a [u8],
+        payload: &'a mut Vec<u8>,
+    ) -> Result<(), WebPushError> {
+        let ikm = self.aes_128_gcm_ikm(shared_secret, as_public_key)?;
+
+        RecordKeys::new(&ikm, salt_bytes)?.seal(0, payload)
+    }
+
+    /// The content encryption key and nonce for aesgcm.
+    fn aes_gcm_keys(
+        &self,
+        shared_secret: &[u8],
+        as_public_key: &[u8],
+        salt_bytes: &[u8],
+    ) -> Result<(Vec<u8>, [u8; 12]), WebPushError> {
         let mut context = Vec::with_capacity(140);
 
         context.extend_from_slice(b"P-256\0");
@@ -183,47 +461,68 @@ impl<'a> HttpEce<'a> {
         context.push((as_public_key.len() & 0xff) as u8);
         context.extend_from_slice(as_public_key);
 
-        let client_auth_secret = hkdf::Salt::new(hkdf::HKDF_SHA256, &self.peer_secret);
-        let salt = hkdf::Salt::new(hkdf::HKDF_SHA256, salt_bytes);
-
-        let EceKey(prk) = client_auth_secret
-            .extract(shared_secret)
-            .expand(&[b"Content-Encoding: auth\0"], EceKey(32))
-            .unwrap()
-            .into();
+        let prk = crypto::hkdf_sha256(
+            self.peer_secret,
+            shared_secret,
+            b"Content-Encoding: auth\0",
+            32,
+        )?;
 
         let mut cek_info = Vec::with_capacity(165);
         cek_info.extend_from_slice(b"Content-Encoding: aesgcm\0");
         cek_info.extend_from_slice(&context);
 
-        let EceKey(content_encryption_key) = salt
-            .extract(&prk)
-            .expand(&[&cek_info], EceKey(16))
-            .unwrap()
-            .into();
+        let content_encryption_key = crypto::hkdf_sha256(salt_bytes, &prk, &cek_info, 16)?;
 
         let mut nonce_info = Vec::with_capacity(164);
         nonce_info.extend_from_slice(b"Content-Encoding: nonce\0");
         nonce_info.extend_from_slice(&context);
 
-        let EceKey(nonce_bytes) = salt
-            .extract(&prk)
-            .expand(&[&nonce_info], EceKey(12))
-            .unwrap()
-            .into();
+        let nonce_bytes = crypto::hkdf_sha256(salt_bytes, &prk, &nonce_info, 12)?;
 
-        let mut nonce = EceNonce::default();
-        nonce.fill(nonce_bytes);
-
-        let unbound_key = aead::UnboundKey::new(&aead::AES_128_GCM, &content_encryption_key)?;
-        let mut sealing_key = aead::SealingKey::new(unbound_key, nonce);
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&nonce_bytes);
 
-        sealing_key.seal_in_place_append_tag(aead::Aad::empty(), payload)?;
+        Ok((content_encryption_key, nonce))
+    }
 
-        Ok(())
+    /// The input keying material for aes128gcm, from which the content
+    /// encryption key and nonce are derived as in any other use of RFC 8188.
+    fn aes_128_gcm_ikm(
+        &self,
+        shared_secret: &[u8],
+        as_public_key: &[u8],
+    ) -> Result<Vec<u8>, WebPushError> {
+        let mut key_info = Vec::with_capacity(144);
+        key_info.extend_from_slice(b"WebPush: info\0");
+        key_info.extend_from_slice(self.peer_public_key);
+        key_info.extend_from_slice(as_public_key);
+
+        crypto::hkdf_sha256(self.peer_secret, shared_secret, &key_info, 32)
     }
 }
 
+/// Finds a parameter such as `dh` from a header value in the form of
+/// `keyid=p256dh;dh=BNoRDbb...,p256ecdsa=BF5oEo...`.
+fn header_param<'h>(
+    headers: &'h [(&'static str, String)],
+    header: &str,
+    param: &str,
+) -> Option<&'h str> {
+    headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(header))
+        .flat_map(|(_, value)| value.split(&[';', ','][..]))
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let key = parts.next()?.trim();
+            let value = parts.next()?.trim();
+            Some((key, value))
+        })
+        .find(|(key, _)| *key == param)
+        .map(|(_, value)| value.trim_matches('"'))
+}
+
 fn front_pad(payload: &[u8], output: &mut [u8]) {
     let payload_len = payload.len();
     let max_payload = output.len() - 2;
@@ -237,23 +536,49 @@ fn front_pad(payload: &[u8], output: &mut [u8]) {
     }
 }
 
+fn front_unpad(payload: &mut Vec<u8>) -> Result<(), WebPushError> {
+    if payload.len() < 2 {
+        return Err(WebPushError::InvalidPadding);
+    }
+
+    let padding_size = ((payload[0] as usize) << 8) | payload[1] as usize;
+
+    if payload.len() < padding_size + 2 || payload[2..padding_size + 2].iter().any(|b| *b != 0) {
+        return Err(WebPushError::InvalidPadding);
+    }
+
+    payload.drain(..padding_size + 2);
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
+    use crate::ece::Header;
+    use crate::error::PayloadSize;
     use crate::error::WebPushError;
-    use crate::http_ece::{front_pad, ContentEncoding, HttpEce};
-    use crate::vapid::VapidSignature;
+    use crate::http_ece::{
+        front_pad, front_unpad, ContentEncoding, FixedKeySource, HttpEce, PaddingPolicy,
+        PayloadBudget,
+    };
+    use crate::message::WebPushPayload;
     use base64::{self, URL_SAFE, URL_SAFE_NO_PAD};
+    use std::convert::TryInto;
+    use std::sync::Arc;
 
     #[test]
     fn test_payload_too_big() {
         let p256dh = base64::decode_config("BLMaF9ffKBiWQLCKvTHb6LO8Nb6dcUh6TItC455vu2kElga6PQvUmaFyCdykxY2nOSSL3yKgfbmFLRTUaGv4yV8",
                                            URL_SAFE).unwrap();
         let auth = base64::decode_config("xS03Fj5ErfTNH_l9WHE9Ig", URL_SAFE).unwrap();
-        let http_ece = HttpEce::new(ContentEncoding::AesGcm, &p256dh, &auth, None);
+        let http_ece = HttpEce::new(ContentEncoding::AesGcm, &p256dh, &auth);
         let content = [0u8; 3801];
 
         assert_eq!(
-            Err(WebPushError::PayloadTooLarge),
+            Err(WebPushError::PayloadTooLarge(Some(PayloadSize {
+                actual: 3801,
+                allowed: 3052
+            }))),
             http_ece.encrypt(&content)
         );
     }
@@ -264,12 +589,44 @@ mod tests {
                                            URL_SAFE).unwrap();
         let auth = base64::decode_config("xS03Fi5ErfTNH_l9WHE9Ig", URL_SAFE).unwrap();
 
-        let http_ece = HttpEce::new(ContentEncoding::Aes128Gcm, &p256dh, &auth, None);
+        let http_ece = HttpEce::new(ContentEncoding::Aes128Gcm, &p256dh, &auth);
         let content = [0u8; 10];
+        let payload = http_ece.encrypt(&content).unwrap();
+
+        assert_eq!("aes128gcm", payload.content_encoding);
+        assert!(payload.crypto_headers.is_empty());
+
+        // salt, record size 4096, key id length 65 and an uncompressed public key
+        assert_eq!(3070, payload.content.len());
+        assert_eq!(&[0, 0, 16, 0, 65, 4], &payload.content[16..22]);
+    }
+
+    #[test]
+    fn test_aes_128_gcm() {
+        // RFC 8291, Appendix A
+        let p256dh = base64::decode_config("BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4",
+                                           URL_SAFE).unwrap();
+        let auth = base64::decode_config("BTBZMqHH6r4Tts7J_aSIgg", URL_SAFE).unwrap();
+        let http_ece = HttpEce::new(ContentEncoding::Aes128Gcm, &p256dh, &auth);
+        let shared_secret =
+            base64::decode_config("kyrL1jIIOHEzg3sM2ZWRHDRB62YACZhhSlknJ672kSs", URL_SAFE).unwrap();
+        let as_pubkey = base64::decode_config("BP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A8",
+                                              URL_SAFE).unwrap();
+        let salt_bytes = base64::decode_config("DGv6ra1nlYgDCS1FRnbzlw", URL_SAFE).unwrap();
+
+        let mut payload = b"When I grow up, I want to be a watermelon\x02".to_vec();
+
+        http_ece
+            .aes_128_gcm(&shared_secret, &as_pubkey, &salt_bytes, &mut payload)
+            .unwrap();
+
+        let header = Header::new(salt_bytes[..].try_into().unwrap(), 4096, &as_pubkey).unwrap();
+        let mut body = header.to_bytes();
+        body.extend_from_slice(&payload);
 
         assert_eq!(
-            Err(WebPushError::NotImplemented),
-            http_ece.encrypt(&content)
+            "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN",
+            base64::encode_config(&body, URL_SAFE_NO_PAD)
         );
     }
 
@@ -278,7 +635,7 @@ mod tests {
         let p256dh = base64::decode_config("BLMbF9ffKBiWQLCKvTHb6LO8Nb6dcUh6TItC455vu2kElga6PQvUmaFyCdykxY2nOSSL3yKgfbmFLRTUaGv4yV8",
                                            URL_SAFE).unwrap();
         let auth = base64::decode_config("xS03Fi5ErfTNH_l9WHE9Ig", URL_SAFE).unwrap();
-        let http_ece = HttpEce::new(ContentEncoding::AesGcm, &p256dh, &auth, None);
+        let http_ece = HttpEce::new(ContentEncoding::AesGcm, &p256dh, &auth);
         let shared_secret =
             base64::decode_config("9vcttSQ8tq-Wi_lLQ_xA37tkYssMtJsdY6xENG5f1sE=", URL_SAFE)
                 .unwrap();
@@ -298,15 +655,7 @@ mod tests {
     }
 
     #[test]
-    fn test_headers_with_vapid() {
-        let as_pubkey =
-            base64::decode_config(
-                "BBXpqeMbtt1iwSoYzs7uRL-QVSKTAuAPrunJoNyW2wMKeVBUyNFCqbkmpVTZOVbqWpwpr_-6TpJvk1qT8T-iOYs=",
-                URL_SAFE
-            ).unwrap();
-
-        let salt_bytes = base64::decode_config("YMcMuxqRkchXwy7vMwNl1Q==", URL_SAFE).unwrap();
-
+    fn test_aes128gcm_headers_without_vapid() {
         let p256dh =
             base64::decode_config(
                 "BLMbF9ffKBiWQLCKvTHb6LO8Nb6dcUh6TItC455vu2kElga6PQvUmaFyCdykxY2nOSSL3yKgfbmFLRTUaGv4yV8",
@@ -315,24 +664,9 @@ mod tests {
 
         let auth = base64::decode_config("xS03Fi5ErfTNH_l9WHE9Ig", URL_SAFE).unwrap();
 
-        let vapid_signature = VapidSignature {
-            auth_t: String::from("foo"),
-            auth_k: String::from("bar"),
-        };
-
-        let http_ece = HttpEce::new(
-            ContentEncoding::AesGcm,
-            &p256dh,
-            &auth,
-            Some(vapid_signature),
-        );
+        let http_ece = HttpEce::new(ContentEncoding::Aes128Gcm, &p256dh, &auth);
 
-        assert_eq!(
-            vec![
-                ("Authorization", "WebPush foo".to_string()),
-                ("Crypto-Key", "dh=BBXpqeMbtt1iwSoYzs7uRL-QVSKTAuAPrunJoNyW2wMKeVBUyNFCqbkmpVTZOVbqWpwpr_-6TpJvk1qT8T-iOYs; p256ecdsa=bar".to_string()),
-                ("Encryption", "salt=YMcMuxqRkchXwy7vMwNl1Q".to_string())],
-            http_ece.generate_headers(&as_pubkey, &salt_bytes))
+        assert!(http_ece.generate_headers(&p256dh, &auth).is_empty());
     }
 
     #[test]
@@ -353,7 +687,7 @@ mod tests {
 
         let auth = base64::decode_config("xS03Fi5ErfTNH_l9WHE9Ig", URL_SAFE).unwrap();
 
-        let http_ece = HttpEce::new(ContentEncoding::AesGcm, &p256dh, &auth, None);
+        let http_ece = HttpEce::new(ContentEncoding::AesGcm, &p256dh, &auth);
 
         assert_eq!(
             vec![
@@ -378,4 +712,343 @@ mod tests {
             output
         );
     }
+
+    #[test]
+    fn test_front_unpad() {
+        let mut payload = vec![0, 3, 0, 0, 0, 110, 97];
+        front_unpad(&mut payload).unwrap();
+        assert_eq!(vec![110, 97], payload);
+
+        let mut payload = vec![0, 3, 0, 1, 0, 110, 97];
+        assert_eq!(Err(WebPushError::InvalidPadding), front_unpad(&mut payload));
+
+        let mut payload = vec![0, 9, 0, 0];
+        assert_eq!(Err(WebPushError::InvalidPadding), front_unpad(&mut payload));
+    }
+
+    // RFC 8291, Appendix A
+    const UA_PRIVATE: &str = "q1dXpw3UpT5VOmu_cf_v6ih07Aems3njxI-JWgLcM94";
+    const UA_PUBLIC: &str =
+        "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4";
+    const UA_AUTH: &str = "BTBZMqHH6r4Tts7J_aSIgg";
+
+    #[test]
+    fn test_decrypt_aes128gcm_rfc8291() {
+        let private_key = base64::decode_config(UA_PRIVATE, URL_SAFE).unwrap();
+        let p256dh = base64::decode_config(UA_PUBLIC, URL_SAFE).unwrap();
+        let auth = base64::decode_config(UA_AUTH, URL_SAFE).unwrap();
+        let http_ece = HttpEce::new(ContentEncoding::Aes128Gcm, &p256dh, &auth);
+
+        let payload = WebPushPayload {
+            content: base64::decode_config("DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN",
+                                           URL_SAFE).unwrap(),
+            crypto_headers: vec![],
+            content_encoding: "aes128gcm",
+        };
+
+        assert_eq!(
+            "When I grow up, I want to be a watermelon".as_bytes(),
+            &http_ece.decrypt(&private_key, &payload).unwrap()[..]
+        );
+    }
+
+    #[test]
+    fn test_decrypt_round_trip() {
+        let private_key = base64::decode_config(UA_PRIVATE, URL_SAFE).unwrap();
+        let p256dh = base64::decode_config(UA_PUBLIC, URL_SAFE).unwrap();
+        let auth = base64::decode_config(UA_AUTH, URL_SAFE).unwrap();
+        let content = "Encrypted payload to be sent in the notification".as_bytes();
+
+        for encoding in [ContentEncoding::AesGcm, ContentEncoding::Aes128Gcm] {
+            let http_ece = HttpEce::new(encoding, &p256dh, &auth);
+            let payload = http_ece.encrypt(content).unwrap();
+
+            assert_eq!(
+                content,
+                &http_ece.decrypt(&private_key, &payload).unwrap()[..]
+            );
+        }
+    }
+
+    #[test]
+    fn test_decrypt_invalid_tag() {
+        let private_key = base64::decode_config(UA_PRIVATE, URL_SAFE).unwrap();
+        let p256dh = base64::decode_config(UA_PUBLIC, URL_SAFE).unwrap();
+        let auth = base64::decode_config(UA_AUTH, URL_SAFE).unwrap();
+
+        for encoding in [ContentEncoding::AesGcm, ContentEncoding::Aes128Gcm] {
+            let http_ece = HttpEce::new(encoding, &p256dh, &auth);
+            let mut payload = http_ece.encrypt(b"naukio").unwrap();
+            let last = payload.content.len() - 1;
+            payload.content[last] ^= 1;
+
+            assert_eq!(
+                Err(WebPushError::InvalidAuthenticationTag),
+                http_ece.decrypt(&private_key, &payload)
+            );
+        }
+    }
+
+    #[test]
+    fn test_decrypt_invalid_padding() {
+        let private_key = base64::decode_config(UA_PRIVATE, URL_SAFE).unwrap();
+        let p256dh = base64::decode_config(UA_PUBLIC, URL_SAFE).unwrap();
+        let auth = base64::decode_config(UA_AUTH, URL_SAFE).unwrap();
+        let http_ece = HttpEce::new(ContentEncoding::Aes128Gcm, &p256dh, &auth);
+        let shared_secret =
+            base64::decode_config("kyrL1jIIOHEzg3sM2ZWRHDRB62YACZhhSlknJ672kSs", URL_SAFE).unwrap();
+        let as_pubkey = base64::decode_config("BP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A8",
+                                              URL_SAFE).unwrap();
+        let salt_bytes = base64::decode_config("DGv6ra1nlYgDCS1FRnbzlw", URL_SAFE).unwrap();
+
+        // no delimiter after the content
+        let mut record = b"naukio".to_vec();
+        http_ece
+            .aes_128_gcm(&shared_secret, &as_pubkey, &salt_bytes, &mut record)
+            .unwrap();
+
+        let header = Header::new(salt_bytes[..].try_into().unwrap(), 4096, &as_pubkey).unwrap();
+        let mut content = header.to_bytes();
+        content.extend_from_slice(&record);
+
+        let payload = WebPushPayload {
+            content,
+            crypto_headers: vec![],
+            content_encoding: "aes128gcm",
+        };
+
+        assert_eq!(
+            Err(WebPushError::InvalidPadding),
+            http_ece.decrypt(&private_key, &payload)
+        );
+    }
+
+    #[test]
+    fn test_decrypt_missing_headers() {
+        let private_key = base64::decode_config(UA_PRIVATE, URL_SAFE).unwrap();
+        let p256dh = base64::decode_config(UA_PUBLIC, URL_SAFE).unwrap();
+        let auth = base64::decode_config(UA_AUTH, URL_SAFE).unwrap();
+        let http_ece = HttpEce::new(ContentEncoding::AesGcm, &p256dh, &auth);
+
+        let mut payload = http_ece.encrypt(b"naukio").unwrap();
+        payload
+            .crypto_headers
+            .retain(|(name, _)| *name != "Encryption");
+
+        assert_eq!(
+            Err(WebPushError::MissingCryptoKeys),
+            http_ece.decrypt(&private_key, &payload)
+        );
+    }
+
+    // RFC 8291, Appendix A, the sender side
+    const AS_PRIVATE: &str = "yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw";
+    const SALT: &str = "DGv6ra1nlYgDCS1FRnbzlw";
+
+    fn fixed_key_source() -> FixedKeySource {
+        let private_key = base64::decode_config(AS_PRIVATE, URL_SAFE).unwrap();
+        let salt = base64::decode_config(SALT, URL_SAFE).unwrap();
+
+        FixedKeySource::new(&private_key, salt[..].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_encrypt_aes128gcm_rfc8291() {
+        let p256dh = base64::decode_config(UA_PUBLIC, URL_SAFE).unwrap();
+        let auth = base64::decode_config(UA_AUTH, URL_SAFE).unwrap();
+        let mut http_ece = HttpEce::new(ContentEncoding::Aes128Gcm, &p256dh, &auth);
+        http_ece.set_key_source(fixed_key_source());
+        http_ece.set_padding_policy(PaddingPolicy::None);
+
+        let payload = http_ece
+            .encrypt(b"When I grow up, I want to be a watermelon")
+            .unwrap();
+
+        assert_eq!(
+            WebPushPayload {
+                content: base64::decode_config("DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN",
+                                               URL_SAFE).unwrap(),
+                crypto_headers: vec![],
+                content_encoding: "aes128gcm",
+            },
+            payload
+        );
+    }
+
+    // draft-ietf-webpush-encryption-04, Appendix A
+    const DRAFT_UA_PRIVATE: &str = "9FWl15_QUQAWDaD3k3l50ZBZQJ4au27F1V4F0uLSD_M";
+    const DRAFT_UA_PUBLIC: &str =
+        "BCEkBjzL8Z3C-oi2Q7oE5t2Np-p7osjGLg93qUP0wvqRT21EEWyf0cQDQcakQMqz4hQKYOQ3il2nNZct4HgAUQU";
+    const DRAFT_UA_AUTH: &str = "R29vIGdvbyBnJyBqb29iIQ";
+    const DRAFT_AS_PRIVATE: &str = "nCScek-QpEjmOOlT-rQ38nZzvdPlqa00Zy0i6m2OJvY";
+    const DRAFT_AS_PUBLIC: &str =
+        "BNoRDbb84JGm8g5Z5CFxurSqsXWJ11ItfXEWYVLE85Y7CYkDjXsIEc4aqxYaQ1G8BqkXCJ6DPpDrWtdWj_mugHU";
+    const DRAFT_SALT: &str = "lngarbyKfMoi9Z75xYXmkg";
+    const DRAFT_BODY: &str = "6nqAQUME8hNqw5J3kl8cpVVJylXKYqZOeseZG8UueKpA";
+
+    #[test]
+    fn test_encrypt_aesgcm_draft_vector() {
+        let p256dh = base64::decode_config(DRAFT_UA_PUBLIC, URL_SAFE).unwrap();
+        let auth = base64::decode_config(DRAFT_UA_AUTH, URL_SAFE).unwrap();
+        let private_key = base64::decode_config(DRAFT_AS_PRIVATE, URL_SAFE).unwrap();
+        let salt = base64::decode_config(DRAFT_SALT, URL_SAFE).unwrap();
+
+        let mut http_ece = HttpEce::new(ContentEncoding::AesGcm, &p256dh, &auth);
+        http_ece.set_key_source(FixedKeySource::new(
+            &private_key,
+            salt[..].try_into().unwrap(),
+        ));
+        http_ece.set_padding_policy(PaddingPolicy::None);
+
+        assert_eq!(
+            WebPushPayload {
+                content: base64::decode_config(DRAFT_BODY, URL_SAFE).unwrap(),
+                crypto_headers: vec![
+                    ("Crypto-Key", format!("dh={}", DRAFT_AS_PUBLIC)),
+                    ("Encryption", format!("salt={}", DRAFT_SALT)),
+                ],
+                content_encoding: "aesgcm",
+            },
+            http_ece.encrypt(b"I am the walrus").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decrypt_aesgcm_draft_vector() {
+        let private_key = base64::decode_config(DRAFT_UA_PRIVATE, URL_SAFE).unwrap();
+        let p256dh = base64::decode_config(DRAFT_UA_PUBLIC, URL_SAFE).unwrap();
+        let auth = base64::decode_config(DRAFT_UA_AUTH, URL_SAFE).unwrap();
+        let http_ece = HttpEce::new(ContentEncoding::AesGcm, &p256dh, &auth);
+
+        // As sent in the draft, with the key ids of the sender.
+        let payload = WebPushPayload {
+            content: base64::decode_config(DRAFT_BODY, URL_SAFE).unwrap(),
+            crypto_headers: vec![
+                ("Crypto-Key", format!("keyid=p256dh;dh={}", DRAFT_AS_PUBLIC)),
+                ("Encryption", format!("keyid=p256dh;salt={}", DRAFT_SALT)),
+            ],
+            content_encoding: "aesgcm",
+        };
+
+        assert_eq!(
+            b"I am the walrus",
+            &http_ece.decrypt(&private_key, &payload).unwrap()[..]
+        );
+    }
+
+    #[test]
+    fn test_encrypt_invalid_fixed_key() {
+        let p256dh = base64::decode_config(UA_PUBLIC, URL_SAFE).unwrap();
+        let auth = base64::decode_config(UA_AUTH, URL_SAFE).unwrap();
+        let mut http_ece = HttpEce::new(ContentEncoding::Aes128Gcm, &p256dh, &auth);
+        http_ece.set_key_source(FixedKeySource::new(&[0u8; 32], [0u8; 16]));
+
+        assert_eq!(
+            Err(WebPushError::InvalidCryptoKeys),
+            http_ece.encrypt(b"naukio")
+        );
+    }
+
+    #[test]
+    fn test_padding_policy() {
+        assert_eq!(0, PaddingPolicy::None.padding_len(20, 3052));
+        assert_eq!(12, PaddingPolicy::PowerOfTwo.padding_len(20, 3052));
+        assert_eq!(0, PaddingPolicy::PowerOfTwo.padding_len(32, 3052));
+        assert_eq!(52, PaddingPolicy::PowerOfTwo.padding_len(3000, 3052));
+        assert_eq!(
+            80,
+            PaddingPolicy::Buckets(vec![1000, 100, 500]).padding_len(20, 3052)
+        );
+        assert_eq!(
+            0,
+            PaddingPolicy::Buckets(vec![100, 500]).padding_len(600, 3052)
+        );
+        assert_eq!(3032, PaddingPolicy::Max.padding_len(20, 3052));
+        assert_eq!(3973, PaddingPolicy::Max.padding_len(20, 3993));
+        assert_eq!(
+            10,
+            PaddingPolicy::Custom(Arc::new(|len| len + 10)).padding_len(20, 3052)
+        );
+        assert_eq!(
+            0,
+            PaddingPolicy::Custom(Arc::new(|_| 0)).padding_len(20, 3052)
+        );
+    }
+
+    #[test]
+    fn test_payload_budget() {
+        let budget = PayloadBudget::new(&ContentEncoding::AesGcm, &PaddingPolicy::Max, 3070);
+        assert_eq!(3052, budget.max_content_len());
+        assert_eq!(3070, budget.max_body_len());
+        assert_eq!(Some(3070), budget.encrypted_len(20));
+        assert_eq!(None, budget.encrypted_len(3053));
+
+        let budget = PayloadBudget::new(&ContentEncoding::AesGcm, &PaddingPolicy::None, 4096);
+        assert_eq!(4078, budget.max_content_len());
+        assert_eq!(Some(38), budget.encrypted_len(20));
+
+        let budget = PayloadBudget::new(
+            &ContentEncoding::Aes128Gcm,
+            &PaddingPolicy::PowerOfTwo,
+            4096,
+        );
+        assert_eq!(3993, budget.max_content_len());
+        assert_eq!(4096, budget.max_body_len());
+        assert_eq!(Some(103 + 2048), budget.encrypted_len(2000));
+        assert_eq!(Some(4096), budget.encrypted_len(3000));
+        assert_eq!(
+            Err(WebPushError::PayloadTooLarge(Some(PayloadSize {
+                actual: 4000,
+                allowed: 3993
+            }))),
+            budget.check(4000)
+        );
+
+        let budget = PayloadBudget::new(&ContentEncoding::Aes128Gcm, &PaddingPolicy::None, 50);
+        assert_eq!(0, budget.max_content_len());
+    }
+
+    #[test]
+    fn test_encrypt_with_body_limit() {
+        let p256dh = base64::decode_config(UA_PUBLIC, URL_SAFE).unwrap();
+        let auth = base64::decode_config(UA_AUTH, URL_SAFE).unwrap();
+
+        for encoding in [ContentEncoding::AesGcm, ContentEncoding::Aes128Gcm] {
+            let mut http_ece = HttpEce::new(encoding, &p256dh, &auth);
+            http_ece.set_body_limit(4096);
+
+            let content = vec![1u8; http_ece.budget().max_content_len()];
+            assert_eq!(4096, http_ece.encrypt(&content).unwrap().content.len());
+            assert_eq!(4096, http_ece.encrypt(b"naukio").unwrap().content.len());
+
+            let content = vec![1u8; http_ece.budget().max_content_len() + 1];
+            assert!(http_ece.encrypt(&content).is_err());
+        }
+    }
+
+    #[test]
+    fn test_encrypt_with_padding_policy() {
+        let private_key = base64::decode_config(UA_PRIVATE, URL_SAFE).unwrap();
+        let p256dh = base64::decode_config(UA_PUBLIC, URL_SAFE).unwrap();
+        let auth = base64::decode_config(UA_AUTH, URL_SAFE).unwrap();
+        let content = [1u8; 20];
+
+        let mut http_ece = HttpEce::new(ContentEncoding::AesGcm, &p256dh, &auth);
+        http_ece.set_padding_policy(PaddingPolicy::None);
+        let payload = http_ece.encrypt(&content).unwrap();
+        assert_eq!(20 + 2 + 16, payload.content.len());
+        assert_eq!(
+            &content,
+            &http_ece.decrypt(&private_key, &payload).unwrap()[..]
+        );
+
+        let mut http_ece = HttpEce::new(ContentEncoding::Aes128Gcm, &p256dh, &auth);
+        http_ece.set_padding_policy(PaddingPolicy::PowerOfTwo);
+        let payload = http_ece.encrypt(&content).unwrap();
+        assert_eq!(86 + 32 + 1 + 16, payload.content.len());
+        assert_eq!(
+            &content,
+            &http_ece.decrypt(&private_key, &payload).unwrap()[..]
+        );
+    }
 }
@@ -1,7 +1,9 @@
 //! # Web Push
 //!
 //! A library for creating and sending push notifications to a web browser. For
-//! content payload encryption it uses the [Encrypted Content-Encoding for HTTP, draft 3](https://datatracker.ietf.org/doc/draft-ietf-httpbis-encryption-encoding/03/?include_text=1).
+//! content payload encryption it uses either the `aes128gcm` [Encrypted Content-Encoding for HTTP](https://tools.ietf.org/html/rfc8188)
+//! with the [Message Encryption for Web Push](https://tools.ietf.org/html/rfc8291),
+//! or the older `aesgcm` from [draft 3](https://datatracker.ietf.org/doc/draft-ietf-httpbis-encryption-encoding/03/?include_text=1).
 //! The client is asynchronious and uses [Tokio](https://tokio.rs) with futures.
 //!
 //! # Example with `features=["http-ureq"]`
@@ -27,7 +29,7 @@
 //! let client = BlockingWebPushClient::new();
 //!
 //! let response = client.send(builder.build()?)?;
-//! println!("Got response: {:?}", response);
+//! println!("Sent, message at {:?}", response.message_uri);
 //! # Ok(())
 //! # }
 //! ```
@@ -42,17 +44,35 @@ extern crate serde_json;
 extern crate log;
 
 mod client;
+mod crypto;
+pub mod ece;
 mod error;
 mod http_ece;
 mod message;
+mod receipt;
+mod retry;
+#[cfg(all(test, any(feature = "ureq", feature = "hyper")))]
+mod test_server;
 mod vapid;
 
 pub use crate::client::*;
-pub use crate::error::WebPushError;
+pub use crate::error::{PayloadSize, WebPushError};
 
 pub use crate::message::{
-    SubscriptionInfo, SubscriptionKeys, WebPushMessage, WebPushMessageBuilder, WebPushPayload,
+    SubscriptionInfo, SubscriptionKeys, Urgency, WebPushMessage, WebPushMessageBuilder,
+    WebPushPayload,
 };
 
-pub use crate::http_ece::ContentEncoding;
-pub use crate::vapid::{VapidKey, VapidSignature, VapidSignatureBuilder};
+pub use crate::receipt::{Receipt, ReceiptSubscription, ReceiptTracker, RECEIPT_RELATION};
+pub use crate::retry::{RetryClass, RetryPolicy};
+
+pub use crate::http_ece::{
+    ContentEncoding, EceKeySource, FixedKeySource, HttpEce, PaddingPolicy, PayloadBudget,
+    SystemKeySource, DEFAULT_BODY_LIMIT,
+};
+pub use crate::vapid::{
+    AsyncEs256Signer, Clock, Es256Signer, FixedClock, LocalProcessSigner, SignFuture, SystemClock,
+    VapidClaims, VapidKey, VapidKeyError, VapidKeyRing, VapidScheme, VapidSignError,
+    VapidSignature, VapidSignatureBuilder, VapidSigner, VapidTokenCache, VapidVerifier,
+    VapidVerifyError, VerifiedToken,
+};
@@ -1,6 +1,8 @@
 use crate::error::WebPushError;
-use crate::http_ece::{ContentEncoding, HttpEce};
-use crate::vapid::VapidSignature;
+use crate::http_ece::{ContentEncoding, HttpEce, PaddingPolicy, DEFAULT_BODY_LIMIT};
+use crate::receipt::ReceiptSubscription;
+use crate::vapid::{VapidScheme, VapidSignature};
+use std::time::Duration;
 
 /// Encryption keys from the client.
 #[derive(Debug, Deserialize, Serialize)]
@@ -49,6 +51,34 @@ pub struct WebPushPayload {
     pub content_encoding: &'static str,
 }
 
+/// How urgently the push service should deliver the message, letting it save
+/// the battery of the device for less urgent ones. See [RFC 8030 section
+/// 5.3](https://tools.ietf.org/html/rfc8030#section-5.3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    /// On power and Wi-Fi, such as advertisements.
+    VeryLow,
+    /// On either power or Wi-Fi, such as topic updates.
+    Low,
+    /// On neither power nor Wi-Fi, such as chat messages. The default of the
+    /// push service when not set.
+    Normal,
+    /// Even on low battery, such as incoming calls.
+    High,
+}
+
+impl Urgency {
+    /// The value of the `Urgency` header.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Urgency::VeryLow => "very-low",
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::High => "high",
+        }
+    }
+}
+
 /// Everything needed to send a push notification to the user.
 #[derive(Debug)]
 pub struct WebPushMessage {
@@ -59,6 +89,19 @@ pub struct WebPushMessage {
     pub ttl: u32,
     /// The encrypted request payload, if sending any data.
     pub payload: Option<WebPushPayload>,
+    /// The urgency of the message, `normal` if not set.
+    pub urgency: Option<Urgency>,
+    /// Replaces a pending message with the same topic on the push service.
+    pub topic: Option<String>,
+    /// The receipt subscription to report the delivery to, if a receipt was
+    /// requested.
+    pub receipt_subscription: Option<String>,
+    /// The time to wait for the push service, instead of the overall timeout
+    /// of the client.
+    pub timeout: Option<Duration>,
+    /// The VAPID headers, sent with and without a payload. A `Crypto-Key`
+    /// here is joined with the one of the payload.
+    pub auth_headers: Vec<(&'static str, String)>,
 }
 
 struct WebPushPayloadBuilder<'a> {
@@ -71,7 +114,14 @@ pub struct WebPushMessageBuilder<'a> {
     subscription_info: &'a SubscriptionInfo,
     payload: Option<WebPushPayloadBuilder<'a>>,
     ttl: u32,
+    urgency: Option<Urgency>,
+    topic: Option<String>,
+    receipt_subscription: Option<String>,
+    timeout: Option<Duration>,
+    padding: PaddingPolicy,
+    body_limit: usize,
     vapid_signature: Option<VapidSignature>,
+    vapid_scheme: Option<VapidScheme>,
 }
 
 impl<'a> WebPushMessageBuilder<'a> {
@@ -85,8 +135,15 @@ impl<'a> WebPushMessageBuilder<'a> {
         Ok(WebPushMessageBuilder {
             subscription_info,
             ttl: 2_419_200,
+            urgency: None,
+            topic: None,
+            receipt_subscription: None,
+            timeout: None,
             payload: None,
+            padding: PaddingPolicy::default(),
+            body_limit: DEFAULT_BODY_LIMIT,
             vapid_signature: None,
+            vapid_scheme: None,
         })
     }
 
@@ -97,14 +154,84 @@ impl<'a> WebPushMessageBuilder<'a> {
         self.ttl = ttl;
     }
 
+    /// How urgently the message should be delivered. If not set, push
+    /// services handle it as `Urgency::Normal`.
+    pub fn set_urgency(&mut self, urgency: Urgency) {
+        self.urgency = Some(urgency);
+    }
+
+    /// A message with a topic replaces an undelivered message with the same
+    /// topic on the push service, such as a newer count of unread messages.
+    /// The topic can be at most 32 characters from the URL safe base64
+    /// alphabet.
+    ///
+    /// ```
+    /// # use web_push::*;
+    /// let subscription_info = SubscriptionInfo::new("https://example.com/push", "", "");
+    /// let mut builder = WebPushMessageBuilder::new(&subscription_info).unwrap();
+    ///
+    /// assert_eq!(Ok(()), builder.set_topic("unread-count"));
+    /// assert_eq!(Err(WebPushError::InvalidTopic), builder.set_topic("unread count"));
+    /// ```
+    pub fn set_topic<S>(&mut self, topic: S) -> Result<(), WebPushError>
+    where
+        S: Into<String>,
+    {
+        let topic = topic.into();
+        let valid_char = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+
+        if topic.is_empty() || topic.len() > 32 || !topic.chars().all(valid_char) {
+            return Err(WebPushError::InvalidTopic);
+        }
+
+        self.topic = Some(topic);
+        Ok(())
+    }
+
+    /// Requests a receipt when the message is delivered, reported to the
+    /// receipt subscription. The push service then answers the send with
+    /// `202 Accepted`.
+    pub fn set_receipt_subscription(&mut self, receipt_subscription: &ReceiptSubscription) {
+        self.receipt_subscription = Some(receipt_subscription.uri.clone());
+    }
+
+    /// How long to wait for the push service to answer this message,
+    /// overriding the overall timeout set with
+    /// [WebPushClientBuilder](struct.WebPushClientBuilder.html).
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = Some(timeout);
+    }
+
     /// Add a VAPID signature to the request. To be generated with the
     /// [VapidSignatureBuilder](struct.VapidSignatureBuilder.html).
     pub fn set_vapid_signature(&mut self, vapid_signature: VapidSignature) {
         self.vapid_signature = Some(vapid_signature);
     }
 
-    /// If set, the client will get content in the notification. Has a maximum size of
-    /// 3800 characters.
+    /// How the VAPID signature is sent. By default `vapid t=..., k=...` of
+    /// RFC 8292 is used with aes128gcm and without a payload, and `WebPush`
+    /// with aesgcm.
+    pub fn set_vapid_scheme(&mut self, vapid_scheme: VapidScheme) {
+        self.vapid_scheme = Some(vapid_scheme);
+    }
+
+    /// How much to pad the payload before encryption. By default every payload
+    /// is padded to the maximum size, hiding its length but making each
+    /// request about 3 kB.
+    pub fn set_padding_policy(&mut self, padding: PaddingPolicy) {
+        self.padding = padding;
+    }
+
+    /// The largest encrypted body the push service accepts, 3070 bytes by
+    /// default. Use [PayloadBudget](struct.PayloadBudget.html) to find out how
+    /// much content fits.
+    pub fn set_body_limit(&mut self, body_limit: usize) {
+        self.body_limit = body_limit;
+    }
+
+    /// If set, the client will get content in the notification. With the
+    /// default body limit it can be at most 3052 bytes for aesgcm and 2967
+    /// bytes for aes128gcm.
     pub fn set_payload(&mut self, encoding: ContentEncoding, payload: &'a [u8]) {
         self.payload = Some(WebPushPayloadBuilder {
             content: payload,
@@ -117,24 +244,166 @@ impl<'a> WebPushMessageBuilder<'a> {
     pub fn build(self) -> Result<WebPushMessage, WebPushError> {
         let endpoint = self.subscription_info.endpoint.clone();
 
+        let auth_headers = match &self.vapid_signature {
+            Some(signature) => {
+                let scheme = match (self.vapid_scheme, &self.payload) {
+                    (Some(scheme), _) => scheme,
+                    (None, Some(payload)) => VapidScheme::for_encoding(&payload.encoding),
+                    (None, None) => VapidScheme::Vapid,
+                };
+
+                signature.headers(scheme)
+            }
+            None => Vec::new(),
+        };
+
         if let Some(payload) = self.payload {
             let p256dh =
                 base64::decode_config(&self.subscription_info.keys.p256dh, base64::URL_SAFE)?;
             let auth = base64::decode_config(&self.subscription_info.keys.auth, base64::URL_SAFE)?;
 
-            let http_ece = HttpEce::new(payload.encoding, &p256dh, &auth, self.vapid_signature);
+            let mut http_ece = HttpEce::new(payload.encoding, &p256dh, &auth);
+            http_ece.set_padding_policy(self.padding);
+            http_ece.set_body_limit(self.body_limit);
 
             Ok(WebPushMessage {
                 endpoint,
                 ttl: self.ttl,
                 payload: Some(http_ece.encrypt(payload.content)?),
+                urgency: self.urgency,
+                topic: self.topic,
+                receipt_subscription: self.receipt_subscription,
+                timeout: self.timeout,
+                auth_headers,
             })
         } else {
             Ok(WebPushMessage {
                 endpoint,
                 ttl: self.ttl,
                 payload: None,
+                urgency: self.urgency,
+                topic: self.topic,
+                receipt_subscription: self.receipt_subscription,
+                timeout: self.timeout,
+                auth_headers,
             })
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::error::WebPushError;
+    use crate::http_ece::ContentEncoding;
+    use crate::message::{SubscriptionInfo, WebPushMessageBuilder};
+    use crate::vapid::{VapidScheme, VapidSignature};
+
+    fn subscription_info() -> SubscriptionInfo {
+        SubscriptionInfo::new(
+            "https://example.com/push",
+            "BLMbF9ffKBiWQLCKvTHb6LO8Nb6dcUh6TItC455vu2kElga6PQvUmaFyCdykxY2nOSSL3yKgfbmFLRTUaGv4yV8",
+            "xS03Fi5ErfTNH_l9WHE9Ig",
+        )
+    }
+
+    fn auth_headers(
+        encoding: Option<ContentEncoding>,
+        scheme: Option<VapidScheme>,
+    ) -> Vec<(&'static str, String)> {
+        let subscription_info = subscription_info();
+        let mut builder = WebPushMessageBuilder::new(&subscription_info).unwrap();
+
+        builder.set_vapid_signature(VapidSignature {
+            auth_t: String::from("foo"),
+            auth_k: String::from("bar"),
+        });
+
+        if let Some(encoding) = encoding {
+            builder.set_payload(encoding, b"test");
+        }
+
+        if let Some(scheme) = scheme {
+            builder.set_vapid_scheme(scheme);
+        }
+
+        builder.build().unwrap().auth_headers
+    }
+
+    #[test]
+    fn test_headers_with_vapid() {
+        assert_eq!(
+            vec![
+                ("Authorization", "WebPush foo".to_string()),
+                ("Crypto-Key", "p256ecdsa=bar".to_string())
+            ],
+            auth_headers(Some(ContentEncoding::AesGcm), None)
+        );
+
+        for encoding in [Some(ContentEncoding::Aes128Gcm), None].iter().copied() {
+            assert_eq!(
+                vec![("Authorization", "vapid t=foo, k=bar".to_string())],
+                auth_headers(encoding, None)
+            );
+        }
+    }
+
+    #[test]
+    fn test_headers_with_vapid_scheme_override() {
+        assert_eq!(
+            vec![("Authorization", "vapid t=foo, k=bar".to_string())],
+            auth_headers(Some(ContentEncoding::AesGcm), Some(VapidScheme::Vapid))
+        );
+
+        assert_eq!(
+            vec![
+                ("Authorization", "WebPush foo".to_string()),
+                ("Crypto-Key", "p256ecdsa=bar".to_string())
+            ],
+            auth_headers(Some(ContentEncoding::Aes128Gcm), Some(VapidScheme::WebPush))
+        );
+    }
+
+    #[test]
+    fn test_encryption_headers_without_vapid() {
+        let subscription_info = subscription_info();
+        let mut builder = WebPushMessageBuilder::new(&subscription_info).unwrap();
+        builder.set_payload(ContentEncoding::AesGcm, b"test");
+
+        let message = builder.build().unwrap();
+        let names: Vec<_> = message
+            .payload
+            .unwrap()
+            .crypto_headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect();
+
+        assert!(message.auth_headers.is_empty());
+        assert_eq!(vec!["Crypto-Key", "Encryption"], names);
+    }
+
+    #[test]
+    fn test_topic_validation() {
+        let subscription_info = SubscriptionInfo::new("https://example.com/push", "", "");
+        let mut builder = WebPushMessageBuilder::new(&subscription_info).unwrap();
+
+        for topic in &["a", "unread_count-42", &"A".repeat(32)] {
+            assert_eq!(Ok(()), builder.set_topic(*topic));
+        }
+
+        for topic in &[
+            "",
+            &"A".repeat(33),
+            "unread count",
+            "a+b",
+            "a/b",
+            "abc=",
+            "ä",
+        ] {
+            assert_eq!(Err(WebPushError::InvalidTopic), builder.set_topic(*topic));
+        }
+
+        // The last valid topic is kept.
+        assert_eq!(Some("A".repeat(32)), builder.build().unwrap().topic);
+    }
+}
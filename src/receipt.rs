@@ -0,0 +1,225 @@
+use crate::client::SendOutcome;
+use std::collections::HashMap;
+use url::Url;
+
+/// The link relation of the receipt subscribe resource.
+pub const RECEIPT_RELATION: &str = "urn:ietf:params:push:receipt";
+
+/// A receipt subscription of the application server, for requesting delivery
+/// receipts of messages as specified in [RFC 8030 section
+/// 5.1](https://tools.ietf.org/html/rfc8030#section-5.1).
+///
+/// Created by the client from the receipt subscribe resource of the push
+/// service, and set on messages with
+/// [set_receipt_subscription](struct.WebPushMessageBuilder.html#method.set_receipt_subscription).
+/// One subscription can be used for any number of messages.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReceiptSubscription {
+    /// The URI of the receipt subscription, sent in the `Push-Receipt`
+    /// header and listened to for receipts.
+    pub uri: String,
+}
+
+impl ReceiptSubscription {
+    /// A subscription created earlier.
+    pub fn new<S>(uri: S) -> ReceiptSubscription
+    where
+        S: Into<String>,
+    {
+        ReceiptSubscription { uri: uri.into() }
+    }
+
+    /// Finds the receipt subscribe resource from a `Link` header of the push
+    /// service, `<uri>; rel="urn:ietf:params:push:receipt"`, resolved
+    /// against the URI of the response.
+    ///
+    /// ```
+    /// # use web_push::*;
+    /// let link = r#"</receipts/subscribe>; rel="urn:ietf:params:push:receipt""#;
+    ///
+    /// assert_eq!(
+    ///     Some(String::from("https://push.example.net/receipts/subscribe")),
+    ///     ReceiptSubscription::subscribe_uri(link, "https://push.example.net/subscribe"),
+    /// );
+    /// ```
+    pub fn subscribe_uri(link: &str, base: &str) -> Option<String> {
+        let base = Url::parse(base).ok()?;
+
+        link.split(',').find_map(|value| {
+            let mut params = value.split(';').map(str::trim);
+            let target = params.next()?.strip_prefix('<')?.strip_suffix('>')?;
+
+            let is_receipt = params.any(|param| {
+                param
+                    .strip_prefix("rel=")
+                    .map(|rel| rel.trim_matches('"').split_whitespace())
+                    .is_some_and(|mut rels| rels.any(|rel| rel == RECEIPT_RELATION))
+            });
+
+            match is_receipt {
+                true => base.join(target).ok().map(String::from),
+                false => None,
+            }
+        })
+    }
+}
+
+/// A delivery receipt from the push service, as read by the
+/// [ReceiptPoller](struct.ReceiptPoller.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Receipt {
+    /// The push message resource of the delivered message, the same as the
+    /// [message_uri](struct.SendOutcome.html#structfield.message_uri) of its
+    /// outcome.
+    pub message_uri: String,
+    /// The status of the poll response that carried the receipt.
+    pub status: u16,
+}
+
+impl Receipt {
+    /// Is the receipt for the message that was sent with the outcome.
+    pub fn is_for(&self, outcome: &SendOutcome) -> bool {
+        outcome.message_uri.as_deref() == Some(self.message_uri.as_str())
+    }
+}
+
+/// Keeps a value, such as a database id, for every message waiting for a
+/// receipt, and hands it back when the receipt arrives.
+///
+/// ```
+/// # use web_push::*;
+/// # fn example(outcome: SendOutcome, receipt: Receipt) {
+/// let mut tracker = ReceiptTracker::new();
+/// tracker.insert(&outcome, "security-alert-42");
+///
+/// if let Some(alert) = tracker.resolve(&receipt) {
+///     println!("{} was delivered", alert);
+/// }
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ReceiptTracker<T> {
+    pending: HashMap<String, T>,
+}
+
+impl<T> Default for ReceiptTracker<T> {
+    fn default() -> Self {
+        ReceiptTracker {
+            pending: HashMap::new(),
+        }
+    }
+}
+
+impl<T> ReceiptTracker<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Waits for a receipt of the message. Returns false if the push service
+    /// did not return a message URI to correlate with.
+    pub fn insert(&mut self, outcome: &SendOutcome, value: T) -> bool {
+        match &outcome.message_uri {
+            Some(message_uri) => {
+                self.pending.insert(message_uri.clone(), value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The value of the message the receipt is for, which is no longer
+    /// pending.
+    pub fn resolve(&mut self, receipt: &Receipt) -> Option<T> {
+        self.pending.remove(&receipt.message_uri)
+    }
+
+    /// How many messages are still waiting for a receipt.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Stops waiting for the receipts, such as for messages that expired.
+    /// Returns the values of the messages.
+    pub fn drain(&mut self) -> impl Iterator<Item = (String, T)> + '_ {
+        self.pending.drain()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::client::SendOutcome;
+    use crate::receipt::{Receipt, ReceiptSubscription, ReceiptTracker};
+    use std::time::Duration;
+
+    fn outcome(message_uri: Option<&str>) -> SendOutcome {
+        SendOutcome {
+            status: 202,
+            message_uri: message_uri.map(String::from),
+            ttl: 60,
+            headers: Vec::new(),
+            latency: Duration::from_millis(10),
+            attempts: 1,
+        }
+    }
+
+    fn receipt(message_uri: &str) -> Receipt {
+        Receipt {
+            message_uri: message_uri.to_string(),
+            status: 204,
+        }
+    }
+
+    #[test]
+    fn test_subscribe_uri() {
+        let base = "https://push.example.net/subscribe/abc";
+        let uri = |link| ReceiptSubscription::subscribe_uri(link, base);
+
+        assert_eq!(
+            Some(String::from("https://receipts.example.net/r")),
+            uri(r#"<https://receipts.example.net/r>; rel="urn:ietf:params:push:receipt""#)
+        );
+        assert_eq!(
+            Some(String::from("https://push.example.net/receipts")),
+            uri(
+                r#"</push/x>; rel="urn:ietf:params:push", </receipts>;rel="urn:ietf:params:push:receipt""#
+            )
+        );
+        assert_eq!(
+            Some(String::from("https://push.example.net/receipts")),
+            uri("</receipts>; rel=urn:ietf:params:push:receipt")
+        );
+        assert_eq!(None, uri(r#"</push/x>; rel="urn:ietf:params:push""#));
+        assert_eq!(None, uri("</receipts>"));
+        assert_eq!(None, uri(""));
+    }
+
+    #[test]
+    fn test_tracker() {
+        let first = outcome(Some("https://push.example.net/m/1"));
+        let second = outcome(Some("https://push.example.net/m/2"));
+
+        let mut tracker = ReceiptTracker::new();
+        assert!(tracker.insert(&first, 1));
+        assert!(tracker.insert(&second, 2));
+        assert!(!tracker.insert(&outcome(None), 3));
+        assert_eq!(2, tracker.pending());
+
+        let delivered = receipt("https://push.example.net/m/2");
+        assert!(delivered.is_for(&second));
+        assert!(!delivered.is_for(&first));
+
+        assert_eq!(Some(2), tracker.resolve(&delivered));
+        assert_eq!(None, tracker.resolve(&delivered));
+        assert_eq!(
+            None,
+            tracker.resolve(&receipt("https://push.example.net/m/3"))
+        );
+
+        let expired: Vec<_> = tracker.drain().collect();
+        assert_eq!(
+            vec![(String::from("https://push.example.net/m/1"), 1)],
+            expired
+        );
+        assert_eq!(0, tracker.pending());
+    }
+}
@@ -0,0 +1,306 @@
+use crate::crypto::{Backend, CryptoBackend};
+use crate::error::WebPushError;
+use std::time::Duration;
+
+/// The kinds of errors a send can be retried on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RetryClass {
+    /// The push service failed with a `5xx` status.
+    ServerError,
+    /// The push service answered `429 Too Many Requests`.
+    TooManyRequests,
+    /// The push service did not answer in time.
+    Timeout,
+}
+
+impl RetryClass {
+    /// The class of the error, if it can be retried at all.
+    pub fn of(error: &WebPushError) -> Option<RetryClass> {
+        match error {
+            WebPushError::ServerError(_) => Some(RetryClass::ServerError),
+            WebPushError::TooManyRequests(_) => Some(RetryClass::TooManyRequests),
+            WebPushError::Timeout => Some(RetryClass::Timeout),
+            _ => None,
+        }
+    }
+}
+
+/// How the clients retry sending a message that failed, set with
+/// [WebPushClientBuilder::set_retry_policy](struct.WebPushClientBuilder.html#method.set_retry_policy).
+///
+/// The same encrypted message is sent again after waiting for the
+/// `Retry-After` of the push service, or if not given an exponential backoff
+/// from the base to the maximum. The backoff is shortened randomly by up to
+/// the jitter, so that clients that failed at once don't all retry at once.
+///
+/// A `Retry-After` longer than the maximum backoff is not waited for, the
+/// error is returned with it instead, so that the message can be queued for
+/// later rather than blocking the sender.
+///
+/// ```
+/// # use web_push::*;
+/// # use std::time::Duration;
+/// let mut policy = RetryPolicy::new(5);
+/// policy.set_backoff(Duration::from_millis(200), Duration::from_secs(10));
+/// policy.set_retry_on(&[RetryClass::ServerError, RetryClass::TooManyRequests]);
+/// policy.set_deadline(Duration::from_secs(60));
+///
+/// let mut builder = WebPushClientBuilder::new();
+/// builder.set_retry_policy(policy);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_backoff: Duration,
+    max_backoff: Duration,
+    jitter: f64,
+    retry_on: Vec<RetryClass>,
+    deadline: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Sends a message at most `max_attempts` times, on all retryable errors,
+    /// with a backoff from half a second to 30 seconds and a jitter of 20%.
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            jitter: 0.2,
+            retry_on: vec![
+                RetryClass::ServerError,
+                RetryClass::TooManyRequests,
+                RetryClass::Timeout,
+            ],
+            deadline: None,
+        }
+    }
+
+    /// The wait before the first retry, doubled for every further one up to
+    /// the maximum. The maximum is also the longest `Retry-After` waited for.
+    pub fn set_backoff(&mut self, base: Duration, max: Duration) {
+        self.base_backoff = base;
+        self.max_backoff = max;
+    }
+
+    /// How much of the backoff may be randomly left out, from `0.0` for none
+    /// to `1.0` for all of it.
+    pub fn set_jitter(&mut self, jitter: f64) {
+        self.jitter = jitter.clamp(0.0, 1.0);
+    }
+
+    /// The errors to retry on.
+    pub fn set_retry_on(&mut self, classes: &[RetryClass]) {
+        self.retry_on = classes.to_vec();
+    }
+
+    /// The time after the first attempt when no more retries are started.
+    pub fn set_deadline(&mut self, deadline: Duration) {
+        self.deadline = Some(deadline);
+    }
+
+    /// The wait before the next attempt, after `attempts` failed with the
+    /// error in `elapsed` time. `None` to give up.
+    #[cfg_attr(not(any(feature = "ureq", feature = "hyper")), allow(dead_code))]
+    pub(crate) fn delay(
+        &self,
+        attempts: u32,
+        error: &WebPushError,
+        elapsed: Duration,
+    ) -> Option<Duration> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+
+        let class = RetryClass::of(error)?;
+
+        if !self.retry_on.contains(&class) {
+            return None;
+        }
+
+        let delay = match error {
+            WebPushError::ServerError(Some(retry_after))
+            | WebPushError::TooManyRequests(Some(retry_after)) => {
+                if *retry_after > self.max_backoff {
+                    return None;
+                }
+
+                *retry_after
+            }
+            _ => self.backoff(attempts),
+        };
+
+        match self.deadline {
+            Some(deadline) if elapsed + delay >= deadline => None,
+            _ => Some(delay),
+        }
+    }
+
+    /// The exponential backoff after `attempts` failed, with jitter.
+    #[cfg_attr(not(any(feature = "ureq", feature = "hyper")), allow(dead_code))]
+    pub(crate) fn backoff(&self, attempts: u32) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(31);
+        let backoff = self
+            .base_backoff
+            .checked_mul(1 << exponent)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff);
+
+        backoff.mul_f64(1.0 - self.jitter * random_fraction())
+    }
+}
+
+/// A random number from 0 to 1 from the crypto backend, or 0 for the full
+/// backoff if it fails.
+#[cfg_attr(not(any(feature = "ureq", feature = "hyper")), allow(dead_code))]
+fn random_fraction() -> f64 {
+    let mut random = [0u8; 8];
+
+    if Backend::fill_random(&mut random).is_err() {
+        return 0.0;
+    }
+
+    (u64::from_be_bytes(random) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::WebPushError;
+    use crate::retry::{RetryClass, RetryPolicy};
+    use std::time::Duration;
+
+    fn policy() -> RetryPolicy {
+        let mut policy = RetryPolicy::new(6);
+        policy.set_backoff(Duration::from_millis(100), Duration::from_millis(500));
+        policy.set_jitter(0.0);
+        policy
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let policy = policy();
+        let delays: Vec<_> = (1..=6)
+            .map(|attempts| policy.delay(attempts, &WebPushError::Timeout, Duration::from_secs(0)))
+            .collect();
+
+        let millis = |ms| Some(Duration::from_millis(ms));
+        assert_eq!(
+            vec![
+                millis(100),
+                millis(200),
+                millis(400),
+                millis(500),
+                millis(500),
+                None
+            ],
+            delays
+        );
+    }
+
+    #[test]
+    fn test_jitter() {
+        let mut policy = policy();
+        policy.set_jitter(0.5);
+
+        for _ in 0..100 {
+            let delay = policy
+                .delay(2, &WebPushError::ServerError(None), Duration::from_secs(0))
+                .unwrap();
+
+            assert!(delay >= Duration::from_millis(100));
+            assert!(delay <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut policy = policy();
+        policy.set_backoff(Duration::from_millis(100), Duration::from_secs(3));
+        let retry_after = Duration::from_secs(3);
+
+        assert_eq!(
+            Some(retry_after),
+            policy.delay(
+                1,
+                &WebPushError::TooManyRequests(Some(retry_after)),
+                Duration::from_secs(0)
+            )
+        );
+        assert_eq!(
+            Some(retry_after),
+            policy.delay(
+                1,
+                &WebPushError::ServerError(Some(retry_after)),
+                Duration::from_secs(0)
+            )
+        );
+    }
+
+    #[test]
+    fn test_retry_after_over_max_backoff() {
+        let policy = policy();
+        let now = Duration::from_secs(0);
+
+        // A day is not waited for, nor shortened to the maximum backoff.
+        for retry_after in &[Duration::from_secs(86400), Duration::from_millis(501)] {
+            assert_eq!(
+                None,
+                policy.delay(1, &WebPushError::TooManyRequests(Some(*retry_after)), now)
+            );
+            assert_eq!(
+                None,
+                policy.delay(1, &WebPushError::ServerError(Some(*retry_after)), now)
+            );
+        }
+
+        assert_eq!(
+            Some(Duration::from_millis(500)),
+            policy.delay(
+                1,
+                &WebPushError::ServerError(Some(Duration::from_millis(500))),
+                now
+            )
+        );
+    }
+
+    #[test]
+    fn test_retry_classes_and_deadline() {
+        let mut policy = policy();
+        let now = Duration::from_secs(0);
+
+        assert_eq!(None, policy.delay(1, &WebPushError::BadRequest(None), now));
+        assert_eq!(None, policy.delay(1, &WebPushError::EndpointNotValid, now));
+
+        policy.set_retry_on(&[RetryClass::ServerError]);
+        assert_eq!(None, policy.delay(1, &WebPushError::Timeout, now));
+        assert!(policy
+            .delay(1, &WebPushError::ServerError(None), now)
+            .is_some());
+
+        policy.set_backoff(Duration::from_millis(100), Duration::from_secs(5));
+        policy.set_deadline(Duration::from_secs(1));
+        assert!(policy
+            .delay(
+                1,
+                &WebPushError::ServerError(None),
+                Duration::from_millis(850)
+            )
+            .is_some());
+        assert_eq!(
+            None,
+            policy.delay(
+                1,
+                &WebPushError::ServerError(None),
+                Duration::from_millis(950)
+            )
+        );
+        assert_eq!(
+            None,
+            policy.delay(
+                1,
+                &WebPushError::ServerError(Some(Duration::from_secs(2))),
+                now
+            )
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! A local stand-in for a push service, recording the requests of the clients
+//! and answering them with a scripted response.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// A request as received by the server.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// The value of the header, by its case-insensitive name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The response to send back.
+#[derive(Debug, Clone)]
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Response {
+    pub fn new(status: u16) -> Response {
+        Response {
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Response {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+type Handler = dyn Fn(&Request) -> Response + Send + Sync;
+
+/// The requests received so far, and a condition for waiting on more.
+type Recorded = (Mutex<Vec<Request>>, Condvar);
+
+/// Serves on a random local port until the test ends. Every connection is
+/// closed after one request.
+pub struct TestServer {
+    port: u16,
+    requests: Arc<Recorded>,
+}
+
+impl TestServer {
+    /// Answers every request with `201 Created`.
+    pub fn new() -> TestServer {
+        Self::with_handler(|_| Response::new(201))
+    }
+
+    pub fn with_handler<H>(handler: H) -> TestServer
+    where
+        H: Fn(&Request) -> Response + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new((Mutex::new(Vec::new()), Condvar::new()));
+        let handler: Arc<Handler> = Arc::new(handler);
+
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let (recorded, handler) = (recorded.clone(), handler.clone());
+                thread::spawn(move || {
+                    if let Ok(stream) = stream {
+                        serve(stream, &recorded, &*handler);
+                    }
+                });
+            }
+        });
+
+        TestServer { port, requests }
+    }
+
+    /// The URL of the path on the server.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    /// The requests received so far, in order. Requests are recorded before
+    /// they are answered.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.0.lock().unwrap().clone()
+    }
+
+    /// Blocks until the server has received `count` requests.
+    pub fn wait_for_requests(&self, count: usize) {
+        let (requests, received) = &*self.requests;
+        let _requests = received
+            .wait_while(requests.lock().unwrap(), |requests| requests.len() < count)
+            .unwrap();
+    }
+}
+
+/// Holds the answers of a handler until released, for push services that
+/// answer too late.
+#[derive(Clone, Default)]
+pub struct Stall {
+    released: Arc<(Mutex<bool>, Condvar)>,
+}
+
+impl Stall {
+    pub fn new() -> Stall {
+        Self::default()
+    }
+
+    /// Blocks the handler until the stall is released.
+    pub fn wait(&self) {
+        let (released, condvar) = &*self.released;
+        let _released = condvar
+            .wait_while(released.lock().unwrap(), |released| !*released)
+            .unwrap();
+    }
+
+    /// Answers the held requests, and all later ones right away.
+    pub fn release(&self) {
+        let (released, condvar) = &*self.released;
+        *released.lock().unwrap() = true;
+        condvar.notify_all();
+    }
+}
+
+fn serve(stream: TcpStream, recorded: &Recorded, handler: &Handler) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut line = String::new();
+
+    if reader.read_line(&mut line).unwrap_or(0) == 0 {
+        return;
+    }
+
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or_default().to_string();
+    let mut headers = Vec::new();
+
+    loop {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+        let header = line.trim_end();
+
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_string(), value.trim().to_string()));
+        }
+    }
+
+    let mut request = Request {
+        method,
+        path,
+        headers,
+        body: Vec::new(),
+    };
+
+    let length: usize = request
+        .header("Content-Length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0);
+    request.body = vec![0; length];
+    reader.read_exact(&mut request.body).unwrap();
+
+    let (requests, received) = recorded;
+    requests.lock().unwrap().push(request.clone());
+    received.notify_all();
+
+    let response = handler(&request);
+
+    let mut head = format!(
+        "HTTP/1.1 {} Stand-in\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        response.body.len()
+    );
+
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+
+    let mut stream = stream;
+    let _ = stream.write_all(format!("{}\r\n{}", head, response.body).as_bytes());
+}
@@ -1,8 +1,11 @@
+use super::clock::{unix_time, Clock, SystemClock};
+use super::signer;
 use super::VapidSignError;
-use super::{VapidKey, VapidSignature, VapidSigner};
+use super::{Es256Signer, VapidSignature, VapidTokenCache};
 use crate::message::SubscriptionInfo;
 use serde_json::Value;
 use std::collections::BTreeMap;
+use std::sync::Arc;
 
 /// A VAPID signature builder for generating a signature for signing a request payload.
 ///
@@ -10,29 +13,22 @@ use std::collections::BTreeMap;
 /// the server and derive a public key from the generated private key to the
 /// client.
 ///
-/// Private key generation:
+/// A new key is created with [VapidKey::generate](struct.VapidKey.html#method.generate)
+/// and can be stored in any of the formats `VapidKey` exports:
 ///
-/// ```bash,ignore
-/// openssl ecparam -name prime256v1 -genkey -noout -out private.pem
-/// ```
-///
-/// To derive a public key out of generated private key:
-///
-/// ```bash,ignore
-/// openssl ec -in private.pem -pubout -out vapid_public.pem
-/// ```
-///
-/// To get the byte form of the public key for the JavaScript client:
+/// ```no_run
+/// # use web_push::VapidKey;
+/// let key = VapidKey::generate().unwrap();
+/// std::fs::write("private.pem", key.to_pkcs8_pem()).unwrap();
 ///
-/// ```bash,ignore
-/// openssl ec -in private.pem -text -noout -conv_form uncompressed
+/// // To be passed to `pushManager.subscribe` in the JavaScript client.
+/// println!("{}", key.application_server_key());
 /// ```
 ///
-/// ... or a base64 encoded string, which the client should convert into
-/// byte form before using:
+/// Keys generated with OpenSSL work too:
 ///
 /// ```bash,ignore
-/// openssl ec -in private.pem -pubout -outform DER|tail -c 65|base64|tr '/+' '_-'|tr -d '\n'
+/// openssl ecparam -name prime256v1 -genkey -noout -out private.pem
 /// ```
 ///
 /// To create a VAPID signature:
@@ -60,10 +56,10 @@ use std::collections::BTreeMap;
 /// let signature = sig_builder.sign(&key).unwrap();
 /// # }
 /// ```
-
 pub struct VapidSignatureBuilder<'a> {
     claims: BTreeMap<&'a str, Value>,
     subscription_info: &'a SubscriptionInfo,
+    clock: Box<dyn Clock + 'a>,
 }
 
 impl<'a> VapidSignatureBuilder<'a> {
@@ -71,12 +67,13 @@ impl<'a> VapidSignatureBuilder<'a> {
         VapidSignatureBuilder {
             claims: BTreeMap::new(),
             subscription_info,
+            clock: Box::new(SystemClock::new()),
         }
     }
 
     /// Add a claim to the signature. Claims `aud` and `exp` are automatically
     /// added to the signature. Add them manually to override the default
-    /// values.
+    /// values. The `sub` claim is required.
     ///
     /// The function accepts any value that can be converted into a type JSON
     /// supports.
@@ -87,22 +84,53 @@ impl<'a> VapidSignatureBuilder<'a> {
         self.claims.insert(key, val.into());
     }
 
+    /// Replaces the system clock used for the default `exp` and for checking
+    /// the claims.
+    pub fn set_clock<C>(&mut self, clock: C)
+    where
+        C: Clock + 'a,
+    {
+        self.clock = Box::new(clock);
+    }
+
     /// Builds a signature to be used in [WebPushMessageBuilder](struct.WebPushMessageBuilder.html).
-    pub fn sign(self, key: &VapidKey) -> Result<VapidSignature, VapidSignError> {
-        let endpoint = self.subscription_info.endpoint.clone();
-        println!("endpoint : {}", endpoint);
-        let signature = VapidSigner::sign(key, &endpoint, self.claims)?;
+    /// Fails if the claims break the rules of RFC 8292, see
+    /// [VapidClaims::validate](struct.VapidClaims.html#method.validate).
+    ///
+    /// The key can be a [VapidKey](struct.VapidKey.html) or any other
+    /// [Es256Signer](trait.Es256Signer.html).
+    pub fn sign<K>(self, key: &K) -> Result<VapidSignature, VapidSignError>
+    where
+        K: Es256Signer + ?Sized,
+    {
+        let now = unix_time(&*self.clock);
+        let claims = signer::endpoint_claims(&self.subscription_info.endpoint, self.claims, now)?;
 
-        Ok(signature)
+        signer::sign_claims(key, &claims)
+    }
+
+    /// Like [sign](#method.sign), but reuses a signature from the cache if
+    /// one was made for the same push service and claims.
+    pub fn sign_cached<K>(
+        self,
+        key: &K,
+        cache: &VapidTokenCache,
+    ) -> Result<Arc<VapidSignature>, VapidSignError>
+    where
+        K: Es256Signer + ?Sized,
+    {
+        let now = unix_time(&*self.clock);
+
+        cache.sign_at(key, &self.subscription_info.endpoint, &self.claims, now)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::vapid::VapidSignatureBuilder;
+    use crate::vapid::{FixedClock, VapidSignError, VapidSignatureBuilder};
     use crate::{message::SubscriptionInfo, VapidKey};
-    use serde_json;
     use std::fs::File;
+    use std::time::{Duration, UNIX_EPOCH};
 
     lazy_static! {
         static ref PRIVATE_PEM: File = File::open("resources/vapid_test_key.pem").unwrap();
@@ -123,9 +151,11 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::explicit_auto_deref)]
     fn test_builder_from_pem() {
         let key = VapidKey::from_pem(&*PRIVATE_PEM).unwrap();
-        let builder = VapidSignatureBuilder::new(&*SUBSCRIPTION_INFO);
+        let mut builder = VapidSignatureBuilder::new(&*SUBSCRIPTION_INFO);
+        builder.add_claim("sub", "mailto:test@example.com");
         let signature = builder.sign(&key).unwrap();
 
         assert_eq!(
@@ -137,9 +167,11 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::explicit_auto_deref)]
     fn test_builder_from_der() {
         let key = VapidKey::from_der(&*PRIVATE_DER).unwrap();
-        let builder = VapidSignatureBuilder::new(&*SUBSCRIPTION_INFO);
+        let mut builder = VapidSignatureBuilder::new(&*SUBSCRIPTION_INFO);
+        builder.add_claim("sub", "mailto:test@example.com");
         let signature = builder.sign(&key).unwrap();
 
         assert_eq!(
@@ -149,4 +181,57 @@ mod tests {
 
         assert!(!signature.auth_t.is_empty());
     }
+
+    #[test]
+    fn test_builder_with_clock() {
+        let key = VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap();
+        let mut builder = VapidSignatureBuilder::new(&SUBSCRIPTION_INFO);
+        builder.add_claim("sub", "mailto:test@example.com");
+        builder.set_clock(FixedClock::new(
+            UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        ));
+        let signature = builder.sign(&key).unwrap();
+
+        let payload = signature.auth_t.split('.').nth(1).unwrap();
+        let claims: serde_json::Value = serde_json::from_slice(
+            &base64::decode_config(payload, base64::URL_SAFE_NO_PAD).unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(json!(1_600_000_000 + 12 * 3600), claims["exp"]);
+        assert_eq!(
+            json!("https://updates.push.services.mozilla.com"),
+            claims["aud"]
+        );
+    }
+
+    #[test]
+    fn test_builder_rejects_invalid_claims() {
+        let key = VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap();
+
+        let builder = VapidSignatureBuilder::new(&SUBSCRIPTION_INFO);
+        assert!(matches!(
+            builder.sign(&key),
+            Err(VapidSignError::MissingSubject)
+        ));
+
+        let mut builder = VapidSignatureBuilder::new(&SUBSCRIPTION_INFO);
+        builder.add_claim("sub", "mailto:test@example.com");
+        builder.add_claim("exp", "tomorrow");
+        assert!(matches!(
+            builder.sign(&key),
+            Err(VapidSignError::InvalidExpiry)
+        ));
+
+        let mut builder = VapidSignatureBuilder::new(&SUBSCRIPTION_INFO);
+        builder.add_claim("sub", "mailto:test@example.com");
+        builder.add_claim("exp", 1_600_000_000 + 48 * 3600);
+        builder.set_clock(FixedClock::new(
+            UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        ));
+        assert!(matches!(
+            builder.sign(&key),
+            Err(VapidSignError::ExpiryTooFar)
+        ));
+    }
 }
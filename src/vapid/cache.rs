@@ -0,0 +1,365 @@
+use super::clock::{unix_time, SystemClock};
+use super::signer::{self, audience};
+use super::{Es256Signer, VapidSignError, VapidSignature};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Re-sign tokens that expire within an hour by default.
+const DEFAULT_MARGIN: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, PartialEq, Eq, Hash)]
+struct TokenKey {
+    public_key: Vec<u8>,
+    audience: String,
+    claims: String,
+}
+
+#[derive(Debug)]
+struct CachedToken {
+    signature: Arc<VapidSignature>,
+    exp: i64,
+}
+
+/// Reuses VAPID signatures for all subscriptions of the same push service.
+///
+/// A token only depends on the key, the origin of the push service and the
+/// claims, so when sending to many subscriptions one signature per push
+/// service is enough. The cache hands out the same signature until its
+/// remaining lifetime drops below the margin, and then signs a new one. It can
+/// be shared between threads. Signing happens outside of the lock, so a slow
+/// signer for one push service does not hold up the others, and threads that
+/// miss the same push service at once may each sign.
+///
+/// ```
+/// # use web_push::*;
+/// # use std::fs::File;
+/// let key = VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap();
+/// let cache = VapidTokenCache::new();
+///
+/// for endpoint in &["https://fcm.googleapis.com/fcm/send/a", "https://fcm.googleapis.com/fcm/send/b"] {
+///     let subscription_info = SubscriptionInfo::new(*endpoint, "", "");
+///
+///     let mut sig_builder = VapidSignatureBuilder::new(&subscription_info);
+///     sig_builder.add_claim("sub", "mailto:test@example.com");
+///
+///     let signature = sig_builder.sign_cached(&key, &cache).unwrap();
+///
+///     let mut builder = WebPushMessageBuilder::new(&subscription_info).unwrap();
+///     builder.set_vapid_signature(VapidSignature::clone(&signature));
+/// }
+///
+/// assert_eq!((1, 1), (cache.hits(), cache.misses()));
+/// ```
+#[derive(Debug)]
+pub struct VapidTokenCache {
+    margin: Duration,
+    tokens: Mutex<HashMap<TokenKey, CachedToken>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for VapidTokenCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VapidTokenCache {
+    /// A cache that re-signs tokens when they expire within an hour.
+    pub fn new() -> Self {
+        Self::with_margin(DEFAULT_MARGIN)
+    }
+
+    /// A cache that re-signs tokens when they expire within `margin`. The
+    /// margin should cover the time it takes to deliver a message and the
+    /// clock difference to the push service.
+    pub fn with_margin(margin: Duration) -> Self {
+        VapidTokenCache {
+            margin,
+            tokens: Mutex::new(HashMap::new()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns a cached signature for the push service of the endpoint, or
+    /// signs a new one. The claims are handled as in
+    /// [VapidSignatureBuilder](struct.VapidSignatureBuilder.html), with `aud`
+    /// and `exp` added if not given and checked before signing.
+    pub fn sign<K>(
+        &self,
+        key: &K,
+        endpoint: &str,
+        claims: &BTreeMap<&str, Value>,
+    ) -> Result<Arc<VapidSignature>, VapidSignError>
+    where
+        K: Es256Signer + ?Sized,
+    {
+        let now = unix_time(&SystemClock::new());
+
+        self.sign_at(key, endpoint, claims, now)
+    }
+
+    /// Signs as if the current time was `now` seconds since the Unix epoch.
+    pub(crate) fn sign_at<K>(
+        &self,
+        key: &K,
+        endpoint: &str,
+        claims: &BTreeMap<&str, Value>,
+        now: i64,
+    ) -> Result<Arc<VapidSignature>, VapidSignError>
+    where
+        K: Es256Signer + ?Sized,
+    {
+        let token_key = TokenKey {
+            public_key: key.public_key().to_vec(),
+            audience: match claims.get("aud") {
+                Some(Value::String(aud)) => aud.clone(),
+                _ => audience(endpoint)?,
+            },
+            claims: serde_json::to_string(claims)?,
+        };
+
+        let margin = self.margin.as_secs() as i64;
+
+        if let Some(token) = self.tokens.lock().unwrap().get(&token_key) {
+            if token.exp - now > margin {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(token.signature.clone());
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let claims = signer::endpoint_claims(endpoint, claims.clone(), now)?;
+        let exp = claims.exp;
+        let signature = Arc::new(signer::sign_claims(key, &claims)?);
+
+        let mut tokens = self.tokens.lock().unwrap();
+        tokens.retain(|_, token| token.exp - now > margin);
+        tokens.insert(
+            token_key,
+            CachedToken {
+                signature: signature.clone(),
+                exp,
+            },
+        );
+
+        Ok(signature)
+    }
+
+    /// How many times a cached signature was returned.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// How many times a new signature had to be created.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vapid::{Es256Signer, VapidKey, VapidSignError, VapidTokenCache};
+    use serde_json::Value;
+    use std::collections::BTreeMap;
+    use std::fs::File;
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const NOW: i64 = 1_600_000_000;
+
+    fn key() -> VapidKey {
+        VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap()
+    }
+
+    fn claims(sub: &str) -> BTreeMap<&'static str, Value> {
+        let mut claims = BTreeMap::new();
+        claims.insert("sub", json!(sub));
+        claims
+    }
+
+    #[test]
+    fn test_reuses_per_audience() {
+        let key = key();
+        let cache = VapidTokenCache::new();
+        let claims = claims("mailto:test@example.com");
+
+        let first = cache
+            .sign_at(&key, "https://fcm.googleapis.com/fcm/send/a", &claims, NOW)
+            .unwrap();
+        let second = cache
+            .sign_at(&key, "https://fcm.googleapis.com/fcm/send/b", &claims, NOW)
+            .unwrap();
+        let mozilla = cache
+            .sign_at(
+                &key,
+                "https://updates.push.services.mozilla.com/wpush/v2/c",
+                &claims,
+                NOW,
+            )
+            .unwrap();
+
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_ne!(first.auth_t, mozilla.auth_t);
+        assert_eq!((1, 2), (cache.hits(), cache.misses()));
+    }
+
+    #[test]
+    fn test_keyed_by_claims_and_key() {
+        let key = key();
+        let other_key = VapidKey::generate().unwrap();
+        let cache = VapidTokenCache::new();
+        let endpoint = "https://fcm.googleapis.com/fcm/send/a";
+
+        let first = cache
+            .sign_at(&key, endpoint, &claims("mailto:a@example.com"), NOW)
+            .unwrap();
+        let other_sub = cache
+            .sign_at(&key, endpoint, &claims("mailto:b@example.com"), NOW)
+            .unwrap();
+        let other_key = cache
+            .sign_at(&other_key, endpoint, &claims("mailto:a@example.com"), NOW)
+            .unwrap();
+
+        assert!(!Arc::ptr_eq(&first, &other_sub));
+        assert!(!Arc::ptr_eq(&first, &other_key));
+        assert_eq!((0, 3), (cache.hits(), cache.misses()));
+    }
+
+    #[test]
+    fn test_resigns_within_margin() {
+        let key = key();
+        let cache = VapidTokenCache::with_margin(Duration::from_secs(600));
+        let claims = claims("mailto:test@example.com");
+        let endpoint = "https://fcm.googleapis.com/fcm/send/a";
+
+        let first = cache.sign_at(&key, endpoint, &claims, NOW).unwrap();
+
+        // Twelve hours minus ten minutes later the token is still valid, but
+        // too close to expiring to hand out.
+        let still_valid = cache
+            .sign_at(&key, endpoint, &claims, NOW + 12 * 3600 - 601)
+            .unwrap();
+        let renewed = cache
+            .sign_at(&key, endpoint, &claims, NOW + 12 * 3600 - 600)
+            .unwrap();
+
+        assert!(Arc::ptr_eq(&first, &still_valid));
+        assert_ne!(first.auth_t, renewed.auth_t);
+        assert_eq!((1, 2), (cache.hits(), cache.misses()));
+    }
+
+    #[test]
+    fn test_signature_verifies() {
+        let key = key();
+        let cache = VapidTokenCache::new();
+        let endpoint = "https://fcm.googleapis.com/fcm/send/a";
+        let signature = cache
+            .sign(&key, endpoint, &claims("mailto:test@example.com"))
+            .unwrap();
+
+        let token = crate::VapidVerifier::verify(
+            endpoint,
+            &format!("vapid t={}, k={}", signature.auth_t, signature.auth_k),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!("https://fcm.googleapis.com", token.aud);
+    }
+
+    #[test]
+    fn test_shared_between_threads() {
+        let key = Arc::new(key());
+        let cache = Arc::new(VapidTokenCache::new());
+
+        let threads: Vec<_> = (0..8)
+            .map(|i| {
+                let (key, cache) = (key.clone(), cache.clone());
+                std::thread::spawn(move || {
+                    let endpoint = format!("https://fcm.googleapis.com/fcm/send/{}", i);
+                    cache
+                        .sign(&key, &endpoint, &claims("mailto:test@example.com"))
+                        .unwrap()
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // Threads which missed at once each signed.
+        assert_eq!(8, cache.hits() + cache.misses());
+        assert!(cache.misses() >= 1);
+    }
+
+    /// Signs only after being released.
+    struct SlowSigner {
+        key: VapidKey,
+        started: Mutex<Sender<()>>,
+        release: Mutex<Receiver<()>>,
+    }
+
+    impl Es256Signer for SlowSigner {
+        fn public_key(&self) -> &[u8] {
+            self.key.public_key()
+        }
+
+        fn sign(&self, message: &[u8]) -> Result<Vec<u8>, VapidSignError> {
+            self.started.lock().unwrap().send(()).unwrap();
+            self.release
+                .lock()
+                .unwrap()
+                .recv_timeout(Duration::from_secs(5))
+                .map_err(|_| VapidSignError::External(String::from("not released")))?;
+
+            Es256Signer::sign(&self.key, message)
+        }
+    }
+
+    #[test]
+    fn test_slow_signer_does_not_block_other_audiences() {
+        let (started_sender, started) = mpsc::channel();
+        let (release, release_receiver) = mpsc::channel();
+        let cache = Arc::new(VapidTokenCache::new());
+
+        let slow = {
+            let cache = cache.clone();
+            let signer = SlowSigner {
+                key: key(),
+                started: Mutex::new(started_sender),
+                release: Mutex::new(release_receiver),
+            };
+
+            std::thread::spawn(move || {
+                cache.sign(
+                    &signer,
+                    "https://fcm.googleapis.com/fcm/send/a",
+                    &claims("mailto:test@example.com"),
+                )
+            })
+        };
+
+        started.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // Signs while the other signature is still pending.
+        assert!(cache
+            .sign(
+                &key(),
+                "https://updates.push.services.mozilla.com/wpush/v2/b",
+                &claims("mailto:test@example.com"),
+            )
+            .is_ok());
+
+        release.send(()).unwrap();
+        assert!(slow.join().unwrap().is_ok());
+        assert_eq!((0, 2), (cache.hits(), cache.misses()));
+    }
+}
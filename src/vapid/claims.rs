@@ -0,0 +1,214 @@
+use super::VapidSignError;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use url::Url;
+
+/// The longest time a token may be valid for, as set by RFC 8292.
+pub(crate) const MAX_EXPIRY_SECONDS: i64 = 24 * 60 * 60;
+
+/// The claims of a VAPID token.
+///
+/// The claims are checked against the rules of RFC 8292 before signing, so
+/// that a token the push service would reject with a bare 401 or 403 fails
+/// with a reason instead.
+///
+/// ```
+/// # use web_push::*;
+/// let mut claims = VapidClaims::new("https://fcm.googleapis.com", 1_600_000_000, "mailto:admin@example.com");
+/// claims.extra.insert(String::from("foo"), "bar".into());
+///
+/// assert_eq!(Ok(()), claims.validate(1_600_000_000 - 3600));
+/// assert_eq!(Err(VapidSignError::Expired), claims.validate(1_600_000_000));
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VapidClaims {
+    /// The origin of the push service, such as `https://fcm.googleapis.com`
+    pub aud: String,
+    /// The expiry as seconds since the Unix epoch, at most 24 hours from the
+    /// time of signing
+    pub exp: i64,
+    /// The contact of the application server, a `mailto:` or `https:` URL
+    pub sub: String,
+    /// Other claims to include in the token
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, Value>,
+}
+
+impl VapidClaims {
+    pub fn new<A, S>(aud: A, exp: i64, sub: S) -> VapidClaims
+    where
+        A: Into<String>,
+        S: Into<String>,
+    {
+        VapidClaims {
+            aud: aud.into(),
+            exp,
+            sub: sub.into(),
+            extra: BTreeMap::new(),
+        }
+    }
+
+    /// Reads the claims from a map, with `aud` and `exp` taken from the
+    /// defaults if not given.
+    pub(crate) fn from_map(
+        claims: BTreeMap<&str, Value>,
+        default_aud: impl FnOnce() -> Result<String, VapidSignError>,
+        default_exp: i64,
+    ) -> Result<VapidClaims, VapidSignError> {
+        let mut aud = None;
+        let mut exp = None;
+        let mut sub = None;
+        let mut extra = BTreeMap::new();
+
+        let string = |value: Value, error| match value {
+            Value::String(value) => Ok(value),
+            _ => Err(error),
+        };
+
+        for (name, value) in claims {
+            match name {
+                "aud" => aud = Some(string(value, VapidSignError::InvalidAudience)?),
+                "exp" => exp = Some(value.as_i64().ok_or(VapidSignError::InvalidExpiry)?),
+                "sub" => sub = Some(string(value, VapidSignError::InvalidSubject)?),
+                _ => {
+                    extra.insert(name.to_string(), value);
+                }
+            }
+        }
+
+        Ok(VapidClaims {
+            aud: match aud {
+                Some(aud) => aud,
+                None => default_aud()?,
+            },
+            exp: exp.unwrap_or(default_exp),
+            sub: sub.ok_or(VapidSignError::MissingSubject)?,
+            extra,
+        })
+    }
+
+    /// Checks the claims against the rules of RFC 8292 at `now`, in seconds
+    /// since the Unix epoch: `aud` has to be an origin, `exp` in the future
+    /// but at most 24 hours away and `sub` a `mailto:` or `https:` URL.
+    pub fn validate(&self, now: i64) -> Result<(), VapidSignError> {
+        match Url::parse(&self.aud) {
+            Ok(url) if url.origin().ascii_serialization() == self.aud => (),
+            _ => return Err(VapidSignError::InvalidAudience),
+        }
+
+        if self.exp <= now {
+            return Err(VapidSignError::Expired);
+        }
+
+        if self.exp > now + MAX_EXPIRY_SECONDS {
+            return Err(VapidSignError::ExpiryTooFar);
+        }
+
+        if !is_valid_subject(&self.sub) {
+            return Err(VapidSignError::InvalidSubject);
+        }
+
+        Ok(())
+    }
+}
+
+/// A `mailto:` or `https:` URL.
+pub(crate) fn is_valid_subject(sub: &str) -> bool {
+    match Url::parse(sub) {
+        Ok(url) => url.scheme() == "mailto" || url.scheme() == "https",
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vapid::{VapidClaims, VapidSignError};
+    use std::collections::BTreeMap;
+
+    const NOW: i64 = 1_600_000_000;
+
+    #[test]
+    fn test_validate() {
+        let claims = VapidClaims::new(
+            "https://fcm.googleapis.com",
+            NOW + 3600,
+            "mailto:admin@example.com",
+        );
+        assert_eq!(Ok(()), claims.validate(NOW));
+
+        let with = |change: &dyn Fn(&mut VapidClaims)| {
+            let mut claims = claims.clone();
+            change(&mut claims);
+            claims.validate(NOW)
+        };
+
+        assert_eq!(Ok(()), with(&|c| c.exp = NOW + 24 * 3600));
+        assert_eq!(
+            Ok(()),
+            with(&|c| c.sub = "https://example.com/contact".into())
+        );
+        assert_eq!(Ok(()), with(&|c| c.aud = "http://localhost:8080".into()));
+
+        assert_eq!(Err(VapidSignError::Expired), with(&|c| c.exp = NOW));
+        assert_eq!(
+            Err(VapidSignError::ExpiryTooFar),
+            with(&|c| c.exp = NOW + 24 * 3600 + 1)
+        );
+        assert_eq!(
+            Err(VapidSignError::InvalidSubject),
+            with(&|c| c.sub = "admin@example.com".into())
+        );
+        assert_eq!(
+            Err(VapidSignError::InvalidSubject),
+            with(&|c| c.sub = "http://example.com".into())
+        );
+        assert_eq!(
+            Err(VapidSignError::InvalidAudience),
+            with(&|c| c.aud = "https://fcm.googleapis.com/fcm/send/abc".into())
+        );
+        assert_eq!(
+            Err(VapidSignError::InvalidAudience),
+            with(&|c| c.aud = "fcm.googleapis.com".into())
+        );
+    }
+
+    #[test]
+    fn test_from_map() {
+        let mut map = BTreeMap::new();
+        map.insert("sub", json!("mailto:admin@example.com"));
+        map.insert("foo", json!("bar"));
+
+        let claims =
+            VapidClaims::from_map(map.clone(), || Ok("https://example.com".into()), NOW).unwrap();
+
+        assert_eq!("https://example.com", claims.aud);
+        assert_eq!(NOW, claims.exp);
+        assert_eq!(Some(&json!("bar")), claims.extra.get("foo"));
+        assert_eq!(
+            json!({"aud": "https://example.com", "exp": NOW, "sub": "mailto:admin@example.com", "foo": "bar"}),
+            serde_json::to_value(&claims).unwrap()
+        );
+
+        let from = |name, value| {
+            let mut map = map.clone();
+            map.insert(name, value);
+            VapidClaims::from_map(map, || Ok("https://example.com".into()), NOW)
+        };
+
+        assert_eq!(
+            Err(VapidSignError::InvalidExpiry),
+            from("exp", json!("tomorrow"))
+        );
+        assert_eq!(Err(VapidSignError::InvalidSubject), from("sub", json!(123)));
+        assert_eq!(
+            Err(VapidSignError::InvalidAudience),
+            from("aud", json!(null))
+        );
+
+        map.remove("sub");
+        assert_eq!(
+            Err(VapidSignError::MissingSubject),
+            VapidClaims::from_map(map, || Ok("https://example.com".into()), NOW)
+        );
+    }
+}
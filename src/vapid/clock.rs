@@ -0,0 +1,53 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The current time for setting and checking the expiry of VAPID tokens.
+///
+/// By default the system time is used. For reproducible tokens in tests a
+/// [FixedClock](struct.FixedClock.html) can be set on the
+/// [VapidSignatureBuilder](struct.VapidSignatureBuilder.html) or the
+/// [VapidSigner](struct.VapidSigner.html).
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+/// Reads the system time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// Always returns the same time.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedClock {
+    now: SystemTime,
+}
+
+impl FixedClock {
+    pub fn new(now: SystemTime) -> FixedClock {
+        FixedClock { now }
+    }
+}
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.now
+    }
+}
+
+/// The time of the clock as seconds since the Unix epoch, as used in `exp`.
+pub(crate) fn unix_time(clock: &dyn Clock) -> i64 {
+    match clock.now().duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(before) => -(before.duration().as_secs() as i64),
+    }
+}
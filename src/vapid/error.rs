@@ -1,13 +1,34 @@
+use crate::error::WebPushError;
+#[cfg(feature = "openssl")]
 use openssl::error::ErrorStack;
 use serde_json::error::Error as JsonError;
 use std::{convert::From, error::Error, fmt, io::Error as IoError};
 
 #[derive(PartialEq, Debug)]
 pub enum VapidKeyError {
-    /// Error in SSL signing
+    /// Error in converting from or to OpenSSL's key types
     SslError,
     /// Error in reading the key file
     IoError,
+    /// The private key is out of range or of the wrong length for P-256
+    InvalidKey,
+    /// Error in generating a new key
+    GenerationError,
+    /// The key is not valid PEM, DER, base64 or JSON
+    InvalidEncoding,
+    /// The key is for another curve than P-256
+    UnsupportedCurve,
+    /// The public key stored with the private key does not belong to it
+    PublicKeyMismatch,
+    /// The JWK has no private key `d`
+    MissingPrivateKey,
+    /// The key is encrypted and has to be read with `from_pkcs8_encrypted`
+    EncryptedKey,
+    /// The key is encrypted with something else than PBES2 using PBKDF2 with
+    /// HMAC-SHA256 and AES-CBC
+    UnsupportedEncryption,
+    /// The passphrase does not decrypt the key
+    WrongPassphrase,
 }
 
 impl From<IoError> for VapidKeyError {
@@ -16,6 +37,7 @@ impl From<IoError> for VapidKeyError {
     }
 }
 
+#[cfg(feature = "openssl")]
 impl From<ErrorStack> for VapidKeyError {
     fn from(_: ErrorStack) -> Self {
         Self::SslError
@@ -25,8 +47,17 @@ impl From<ErrorStack> for VapidKeyError {
 impl Error for VapidKeyError {
     fn description(&self) -> &str {
         match *self {
-            Self::SslError => "Error signing with SSL",
+            Self::SslError => "Error converting the key with SSL",
             Self::IoError => "Error opening a file",
+            Self::InvalidKey => "The private key is not valid for P-256",
+            Self::GenerationError => "Error generating a new key",
+            Self::InvalidEncoding => "The key is not valid PEM, DER, base64 or JSON",
+            Self::UnsupportedCurve => "The key is not for the P-256 curve",
+            Self::PublicKeyMismatch => "The public key does not match the private key",
+            Self::MissingPrivateKey => "The key has no private key",
+            Self::EncryptedKey => "The key is encrypted, it has to be read with a passphrase",
+            Self::UnsupportedEncryption => "The key is encrypted with an unsupported algorithm",
+            Self::WrongPassphrase => "The passphrase does not decrypt the key",
         }
     }
 
@@ -37,7 +68,10 @@ impl Error for VapidKeyError {
 
 impl fmt::Display for VapidKeyError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "VapidKeyError: {}", self)
+        #[allow(deprecated)]
+        let description = self.description();
+
+        write!(f, "VapidKeyError: {}", description)
     }
 }
 
@@ -46,8 +80,41 @@ pub enum VapidSignError {
     /// Error in SSL signing
     SslError,
     ClaimSerialization,
+    /// The key could not be used for signing
+    Signing,
+    /// The endpoint is not a valid URL
+    InvalidEndpoint,
+    /// The `aud` claim is not the origin of a push service
+    InvalidAudience,
+    /// The `exp` claim is not a number
+    InvalidExpiry,
+    /// The `exp` claim is in the past
+    Expired,
+    /// The `exp` claim is more than 24 hours in the future
+    ExpiryTooFar,
+    /// There is no `sub` claim
+    MissingSubject,
+    /// The `sub` claim is not a `mailto:` or `https:` URL
+    InvalidSubject,
+    /// The signature does not match the public key of the signer
+    InvalidSignature,
+    /// The signer only signs asynchronously
+    AsyncSigner,
+    /// An external signer failed, with its reason
+    External(String),
+    /// The key of the subscription is not in the key ring
+    UnknownKey,
+    /// The subscription has no key id and the key ring has no default key
+    MissingKeyId,
 }
 
+impl From<WebPushError> for VapidSignError {
+    fn from(_: WebPushError) -> Self {
+        Self::Signing
+    }
+}
+
+#[cfg(feature = "openssl")]
 impl From<ErrorStack> for VapidSignError {
     fn from(_: ErrorStack) -> Self {
         Self::SslError
@@ -65,6 +132,19 @@ impl Error for VapidSignError {
         match *self {
             Self::SslError => "Error signing with SSL",
             Self::ClaimSerialization => "Error serializing claims",
+            Self::Signing => "Error signing the claims",
+            Self::InvalidEndpoint => "The endpoint is not a valid URL",
+            Self::InvalidAudience => "The audience is not the origin of a push service",
+            Self::InvalidExpiry => "The expiry is not a number of seconds",
+            Self::Expired => "The expiry is in the past",
+            Self::ExpiryTooFar => "The expiry is more than 24 hours from now",
+            Self::MissingSubject => "The subject is missing",
+            Self::InvalidSubject => "The subject is not a mailto: or https: URL",
+            Self::InvalidSignature => "The signature does not match the public key",
+            Self::AsyncSigner => "The signer can only be used asynchronously",
+            Self::External(_) => "The external signer failed",
+            Self::UnknownKey => "The key of the subscription is not in the key ring",
+            Self::MissingKeyId => "The subscription has no key id and there is no default key",
         }
     }
 
@@ -75,6 +155,84 @@ impl Error for VapidSignError {
 
 impl fmt::Display for VapidSignError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "VapidSignError: {}", self)
+        #[allow(deprecated)]
+        let description = self.description();
+
+        match self {
+            Self::External(reason) => write!(f, "VapidSignError: {}: {}", description, reason),
+            _ => write!(f, "VapidSignError: {}", description),
+        }
+    }
+}
+
+/// The reason a VAPID token was rejected by the
+/// [VapidVerifier](struct.VapidVerifier.html).
+#[derive(PartialEq, Debug)]
+pub enum VapidVerifyError {
+    /// The `Authorization` header is not in the `vapid t=..., k=...` or
+    /// `WebPush ...` form
+    MalformedHeader,
+    /// There is no `k` parameter, or no `p256ecdsa` in the `Crypto-Key` header
+    MissingPublicKey,
+    /// The public key is not an uncompressed P-256 point in URL-safe base64
+    InvalidPublicKey,
+    /// The token is not three URL-safe base64 encoded parts with JSON in the
+    /// first two
+    MalformedToken,
+    /// The token is signed with something else than ES256
+    UnsupportedAlgorithm,
+    /// The signature does not match the token and the public key
+    InvalidSignature,
+    /// The endpoint is not a valid URL
+    InvalidEndpoint,
+    /// The `aud` claim is not the origin of the endpoint
+    AudienceMismatch,
+    /// The `exp` claim is in the past
+    Expired,
+    /// The `exp` claim is more than 24 hours in the future
+    ExpiryTooFar,
+    /// The `sub` claim is not a `mailto:` or `https:` URL
+    InvalidSubject,
+    /// A required claim is missing
+    MissingClaim(&'static str),
+    /// A claim has the wrong type
+    InvalidClaim(&'static str),
+}
+
+impl Error for VapidVerifyError {
+    fn description(&self) -> &str {
+        match *self {
+            Self::MalformedHeader => "The Authorization header is not a VAPID token",
+            Self::MissingPublicKey => "The public key is missing",
+            Self::InvalidPublicKey => "The public key is not a valid P-256 key",
+            Self::MalformedToken => "The token is not a valid JWT",
+            Self::UnsupportedAlgorithm => "The token is not signed with ES256",
+            Self::InvalidSignature => "The signature does not match the public key",
+            Self::InvalidEndpoint => "The endpoint is not a valid URL",
+            Self::AudienceMismatch => "The audience is not the origin of the endpoint",
+            Self::Expired => "The token has expired",
+            Self::ExpiryTooFar => "The token expires more than 24 hours from now",
+            Self::InvalidSubject => "The subject is not a mailto: or https: URL",
+            Self::MissingClaim(_) => "A required claim is missing",
+            Self::InvalidClaim(_) => "A claim has the wrong type",
+        }
+    }
+
+    fn cause(&self) -> Option<&dyn Error> {
+        None
+    }
+}
+
+impl fmt::Display for VapidVerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        #[allow(deprecated)]
+        let description = self.description();
+
+        match self {
+            Self::MissingClaim(claim) | Self::InvalidClaim(claim) => {
+                write!(f, "VapidVerifyError: {}: {}", description, claim)
+            }
+            _ => write!(f, "VapidVerifyError: {}", description),
+        }
     }
 }
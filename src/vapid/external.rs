@@ -0,0 +1,364 @@
+use super::{VapidKey, VapidSignError};
+use crate::crypto::{Backend, CryptoBackend};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{mpsc, Arc, Condvar, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// The future returned by [AsyncEs256Signer::sign_async](trait.AsyncEs256Signer.html#tymethod.sign_async).
+pub type SignFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Vec<u8>, VapidSignError>> + Send + 'a>>;
+
+/// Signs VAPID tokens with a P-256 key that does not have to be in the
+/// memory of the process, such as a key in a KMS, in an HSM through PKCS#11
+/// or in a sidecar process.
+///
+/// The signature may be returned either as the 64 bytes `r || s` or in the
+/// DER form most key management services use. It is checked against the
+/// public key before use.
+///
+/// [VapidKey](struct.VapidKey.html) implements the trait with the key in
+/// memory.
+pub trait Es256Signer: Send + Sync {
+    /// The uncompressed public key, 65 bytes starting with `0x04`.
+    fn public_key(&self) -> &[u8];
+
+    /// Signs the message with ECDSA using P-256 and SHA-256. The message is
+    /// the JWT signing input, not a digest.
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, VapidSignError>;
+}
+
+/// The asynchronous version of [Es256Signer](trait.Es256Signer.html), for
+/// signers reached over the network.
+pub trait AsyncEs256Signer: Send + Sync {
+    /// The uncompressed public key, 65 bytes starting with `0x04`.
+    fn public_key(&self) -> &[u8];
+
+    /// Signs the message with ECDSA using P-256 and SHA-256. The message is
+    /// the JWT signing input, not a digest.
+    fn sign_async<'a>(&'a self, message: &'a [u8]) -> SignFuture<'a>;
+}
+
+impl Es256Signer for VapidKey {
+    fn public_key(&self) -> &[u8] {
+        VapidKey::public_key(self)
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, VapidSignError> {
+        Ok(Backend::es256_sign(self.private_key(), self.public_key(), message)?.to_vec())
+    }
+}
+
+impl AsyncEs256Signer for VapidKey {
+    fn public_key(&self) -> &[u8] {
+        VapidKey::public_key(self)
+    }
+
+    fn sign_async<'a>(&'a self, message: &'a [u8]) -> SignFuture<'a> {
+        Box::pin(std::future::ready(Es256Signer::sign(self, message)))
+    }
+}
+
+impl<S> Es256Signer for Arc<S>
+where
+    S: Es256Signer + ?Sized,
+{
+    fn public_key(&self) -> &[u8] {
+        (**self).public_key()
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, VapidSignError> {
+        (**self).sign(message)
+    }
+}
+
+impl<S> AsyncEs256Signer for Arc<S>
+where
+    S: AsyncEs256Signer + ?Sized,
+{
+    fn public_key(&self) -> &[u8] {
+        (**self).public_key()
+    }
+
+    fn sign_async<'a>(&'a self, message: &'a [u8]) -> SignFuture<'a> {
+        (**self).sign_async(message)
+    }
+}
+
+/// An example of an external signer: the key is kept by a thread of its own
+/// and signing requests go through a channel, the way a signer in a sidecar
+/// process or a KMS is reached. Meant for tests and as a template for real
+/// implementations.
+///
+/// ```
+/// # use web_push::*;
+/// let signer = LocalProcessSigner::new(VapidKey::generate().unwrap());
+/// let vapid_signer = VapidSigner::new(signer, "mailto:admin@example.com");
+///
+/// let signature = vapid_signer
+///     .sign_for_audience("https://fcm.googleapis.com")
+///     .unwrap();
+/// ```
+pub struct LocalProcessSigner {
+    public_key: Vec<u8>,
+    requests: Mutex<mpsc::Sender<Request>>,
+}
+
+struct Request {
+    message: Vec<u8>,
+    reply: Arc<Reply>,
+}
+
+/// Where the signing thread puts the result, for both blocking and async
+/// callers to pick up.
+#[derive(Default)]
+struct Reply {
+    state: Mutex<ReplyState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct ReplyState {
+    result: Option<Result<Vec<u8>, VapidSignError>>,
+    waker: Option<Waker>,
+}
+
+impl Reply {
+    fn set(&self, result: Result<Vec<u8>, VapidSignError>) {
+        let mut state = self.state.lock().unwrap();
+        state.result = Some(result);
+
+        if let Some(waker) = state.waker.take() {
+            waker.wake();
+        }
+
+        self.ready.notify_all();
+    }
+
+    fn wait(&self) -> Result<Vec<u8>, VapidSignError> {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            match state.result.take() {
+                Some(result) => return result,
+                None => state = self.ready.wait(state).unwrap(),
+            }
+        }
+    }
+}
+
+struct ReplyFuture(Arc<Reply>);
+
+impl Future for ReplyFuture {
+    type Output = Result<Vec<u8>, VapidSignError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut state = self.0.state.lock().unwrap();
+
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl LocalProcessSigner {
+    /// Moves the key to a new signing thread, which stops when the signer is
+    /// dropped.
+    pub fn new(key: VapidKey) -> LocalProcessSigner {
+        let public_key = key.public_key().to_vec();
+        let (requests, receiver) = mpsc::channel::<Request>();
+
+        thread::spawn(move || {
+            for request in receiver {
+                request.reply.set(Es256Signer::sign(&key, &request.message));
+            }
+        });
+
+        LocalProcessSigner {
+            public_key,
+            requests: Mutex::new(requests),
+        }
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    fn request(&self, message: &[u8]) -> Result<Arc<Reply>, VapidSignError> {
+        let reply = Arc::new(Reply::default());
+        let request = Request {
+            message: message.to_vec(),
+            reply: reply.clone(),
+        };
+
+        self.requests
+            .lock()
+            .unwrap()
+            .send(request)
+            .map_err(|_| VapidSignError::External(String::from("the signing thread stopped")))?;
+
+        Ok(reply)
+    }
+}
+
+impl Es256Signer for LocalProcessSigner {
+    fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    fn sign(&self, message: &[u8]) -> Result<Vec<u8>, VapidSignError> {
+        self.request(message)?.wait()
+    }
+}
+
+impl AsyncEs256Signer for LocalProcessSigner {
+    fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    fn sign_async<'a>(&'a self, message: &'a [u8]) -> SignFuture<'a> {
+        match self.request(message) {
+            Ok(reply) => Box::pin(ReplyFuture(reply)),
+            Err(error) => Box::pin(std::future::ready(Err(error))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsyncEs256Signer, Es256Signer, LocalProcessSigner, SignFuture};
+    use crate::vapid::{VapidKey, VapidSignError, VapidSigner, VapidVerifier};
+    use std::fs::File;
+
+    const ENDPOINT: &str = "https://fcm.googleapis.com/fcm/send/abc";
+
+    fn key() -> VapidKey {
+        VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap()
+    }
+
+    fn assert_verifies(signature: &crate::VapidSignature) {
+        let authorization = format!("vapid t={}, k={}", signature.auth_t, signature.auth_k);
+        VapidVerifier::verify(ENDPOINT, &authorization, None).unwrap();
+    }
+
+    /// Returns DER signatures like most key management services.
+    struct DerSigner(VapidKey);
+
+    impl Es256Signer for DerSigner {
+        fn public_key(&self) -> &[u8] {
+            self.0.public_key()
+        }
+
+        fn sign(&self, message: &[u8]) -> Result<Vec<u8>, VapidSignError> {
+            let raw = Es256Signer::sign(&self.0, message)?;
+            let mut sequence = Vec::new();
+
+            for half in raw.chunks(32) {
+                let mut integer = vec![0];
+                integer.extend(half.iter().skip_while(|b| **b == 0));
+
+                if integer[1] & 0x80 == 0 {
+                    integer.remove(0);
+                }
+
+                sequence.extend_from_slice(&[0x02, integer.len() as u8]);
+                sequence.extend(integer);
+            }
+
+            let mut der = vec![0x30, sequence.len() as u8];
+            der.extend(sequence);
+            Ok(der)
+        }
+    }
+
+    /// Signs with another key than the one it claims to have.
+    struct WrongKeySigner(VapidKey, VapidKey);
+
+    impl Es256Signer for WrongKeySigner {
+        fn public_key(&self) -> &[u8] {
+            self.0.public_key()
+        }
+
+        fn sign(&self, message: &[u8]) -> Result<Vec<u8>, VapidSignError> {
+            Es256Signer::sign(&self.1, message)
+        }
+    }
+
+    /// Only available asynchronously.
+    struct RemoteSigner(LocalProcessSigner);
+
+    impl AsyncEs256Signer for RemoteSigner {
+        fn public_key(&self) -> &[u8] {
+            self.0.public_key()
+        }
+
+        fn sign_async<'a>(&'a self, message: &'a [u8]) -> SignFuture<'a> {
+            self.0.sign_async(message)
+        }
+    }
+
+    #[test]
+    fn test_local_process_signer() {
+        let signer = VapidSigner::new(LocalProcessSigner::new(key()), "mailto:admin@example.com");
+
+        assert_eq!(key().public_key(), signer.public_key());
+        assert_verifies(&signer.sign_for_endpoint(ENDPOINT).unwrap());
+
+        // Clones share the signing thread.
+        let clone = signer.clone();
+        let thread = std::thread::spawn(move || clone.sign_for_endpoint(ENDPOINT).unwrap());
+        assert_verifies(&thread.join().unwrap());
+    }
+
+    #[test]
+    fn test_der_signatures() {
+        let signer = VapidSigner::new(DerSigner(key()), "mailto:admin@example.com");
+
+        for _ in 0..20 {
+            assert_verifies(&signer.sign_for_endpoint(ENDPOINT).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let signer = VapidSigner::new(
+            WrongKeySigner(key(), VapidKey::generate().unwrap()),
+            "mailto:admin@example.com",
+        );
+
+        assert_eq!(
+            Some(VapidSignError::InvalidSignature),
+            signer.sign_for_endpoint(ENDPOINT).err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_signers() {
+        let remote = VapidSigner::new_async(
+            RemoteSigner(LocalProcessSigner::new(key())),
+            "mailto:admin@example.com",
+        );
+        assert_verifies(&remote.sign_for_endpoint_async(ENDPOINT).await.unwrap());
+        assert_eq!(
+            Some(VapidSignError::AsyncSigner),
+            remote.sign_for_endpoint(ENDPOINT).err()
+        );
+
+        let in_memory = VapidSigner::new_async(key(), "mailto:admin@example.com");
+        assert_verifies(&in_memory.sign_for_endpoint_async(ENDPOINT).await.unwrap());
+
+        // A blocking signer works asynchronously too.
+        let blocking = VapidSigner::new(key(), "mailto:admin@example.com");
+        assert_verifies(
+            &blocking
+                .sign_for_audience_async("https://fcm.googleapis.com")
+                .await
+                .unwrap(),
+        );
+    }
+}
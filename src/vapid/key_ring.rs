@@ -0,0 +1,365 @@
+use super::{VapidSignError, VapidSignature, VapidSigner};
+use crate::message::SubscriptionInfo;
+use base64::URL_SAFE_NO_PAD;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+struct RingKey {
+    tenant: String,
+    signer: VapidSigner,
+    retired: bool,
+    /// Order of insertion, the newest active key of a tenant is its current
+    /// key.
+    serial: u64,
+}
+
+/// Several VAPID identities, indexed by their public key.
+///
+/// A subscription only works with the `applicationServerKey` it was created
+/// with, so during key rotation the old and the new key are both needed. The
+/// ring keeps the key id of each subscription by its endpoint, registered
+/// with [set_subscription_key](#method.set_subscription_key), and picks the
+/// signer by it. Subscriptions without a key id use the default key, which is
+/// the only key of the ring if there is just one, or the one set with
+/// [set_default](#method.set_default).
+///
+/// Each key belongs to a tenant, so that several applications with their own
+/// identity can share a ring. The newest key of a tenant that is not retired
+/// is the one to give to new subscriptions. Retired keys still sign, so that
+/// their subscriptions keep working until they are migrated.
+///
+/// ```
+/// # use web_push::*;
+/// let old_key = VapidKey::generate().unwrap();
+/// let old_id = old_key.application_server_key();
+///
+/// let mut ring = VapidKeyRing::new();
+/// ring.insert("news", VapidSigner::new(old_key, "mailto:news@example.com"));
+/// ring.insert(
+///     "news",
+///     VapidSigner::new(VapidKey::generate().unwrap(), "mailto:news@example.com"),
+/// );
+/// ring.retire(&old_id);
+///
+/// let subscription_info = SubscriptionInfo::new("https://fcm.googleapis.com/fcm/send/abc", "", "");
+/// ring.set_subscription_key(&subscription_info.endpoint, &old_id).unwrap();
+///
+/// // Still signed with the old key.
+/// let signature = ring.sign(&subscription_info).unwrap();
+/// assert_eq!(old_id, signature.auth_k);
+///
+/// // But due for a new subscription.
+/// assert_eq!(1, ring.on_retired_keys(&[subscription_info]).len());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct VapidKeyRing {
+    keys: HashMap<Vec<u8>, RingKey>,
+    /// The public key of each subscription, by endpoint.
+    subscriptions: HashMap<String, Vec<u8>>,
+    default: Option<Vec<u8>>,
+    serial: u64,
+}
+
+impl VapidKeyRing {
+    pub fn new() -> VapidKeyRing {
+        Self::default()
+    }
+
+    /// Adds the signer of a tenant, which becomes the current key of the
+    /// tenant. Returns the key id, the `applicationServerKey` of the signer.
+    /// A signer with the same public key is replaced.
+    pub fn insert<T>(&mut self, tenant: T, signer: VapidSigner) -> String
+    where
+        T: Into<String>,
+    {
+        let public_key = signer.public_key().to_vec();
+        let key_id = base64::encode_config(&public_key, URL_SAFE_NO_PAD);
+
+        self.serial += 1;
+        self.keys.insert(
+            public_key,
+            RingKey {
+                tenant: tenant.into(),
+                signer,
+                retired: false,
+                serial: self.serial,
+            },
+        );
+
+        key_id
+    }
+
+    /// Marks the key as retired. Returns false if the key is not in the ring.
+    pub fn retire(&mut self, key_id: &str) -> bool {
+        let key = public_key(key_id).and_then(|public_key| self.keys.get_mut(&public_key));
+
+        match key {
+            Some(key) => {
+                key.retired = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the key, once no subscription uses it anymore.
+    pub fn remove(&mut self, key_id: &str) -> Option<VapidSigner> {
+        let public_key = public_key(key_id)?;
+
+        if self.default.as_ref() == Some(&public_key) {
+            self.default = None;
+        }
+
+        self.keys.remove(&public_key).map(|key| key.signer)
+    }
+
+    /// The key for subscriptions without a key id, such as the ones stored
+    /// before the application started keeping track of their keys.
+    pub fn set_default(&mut self, key_id: &str) -> Result<(), VapidSignError> {
+        match public_key(key_id) {
+            Some(public_key) if self.keys.contains_key(&public_key) => {
+                self.default = Some(public_key);
+                Ok(())
+            }
+            _ => Err(VapidSignError::UnknownKey),
+        }
+    }
+
+    /// Records the key id, the `applicationServerKey`, the subscription with
+    /// the endpoint was created with. Fails with `UnknownKey` if the key is
+    /// not in the ring.
+    pub fn set_subscription_key(
+        &mut self,
+        endpoint: &str,
+        key_id: &str,
+    ) -> Result<(), VapidSignError> {
+        match public_key(key_id) {
+            Some(public_key) if self.keys.contains_key(&public_key) => {
+                self.subscriptions.insert(endpoint.to_string(), public_key);
+                Ok(())
+            }
+            _ => Err(VapidSignError::UnknownKey),
+        }
+    }
+
+    /// Forgets the key of the subscription, once it is gone or subscribed
+    /// again. Returns false if no key was recorded for the endpoint.
+    pub fn remove_subscription(&mut self, endpoint: &str) -> bool {
+        self.subscriptions.remove(endpoint).is_some()
+    }
+
+    /// Is the key retired. Unknown keys are not.
+    pub fn is_retired(&self, key_id: &str) -> bool {
+        public_key(key_id)
+            .and_then(|public_key| self.keys.get(&public_key))
+            .is_some_and(|key| key.retired)
+    }
+
+    /// The newest key of the tenant that is not retired, to create new
+    /// subscriptions with.
+    pub fn current(&self, tenant: &str) -> Option<&VapidSigner> {
+        self.keys
+            .values()
+            .filter(|key| key.tenant == tenant && !key.retired)
+            .max_by_key(|key| key.serial)
+            .map(|key| &key.signer)
+    }
+
+    /// The tenant the key of the subscription belongs to.
+    pub fn tenant(&self, subscription_info: &SubscriptionInfo) -> Result<&str, VapidSignError> {
+        Ok(&self.find(subscription_info)?.tenant)
+    }
+
+    /// The signer for the key the subscription was created with.
+    pub fn signer(
+        &self,
+        subscription_info: &SubscriptionInfo,
+    ) -> Result<&VapidSigner, VapidSignError> {
+        Ok(&self.find(subscription_info)?.signer)
+    }
+
+    /// Signs for the endpoint of the subscription with its key. Signers that
+    /// only sign asynchronously are used through [signer](#method.signer).
+    pub fn sign(
+        &self,
+        subscription_info: &SubscriptionInfo,
+    ) -> Result<VapidSignature, VapidSignError> {
+        self.signer(subscription_info)?
+            .sign_for_endpoint(&subscription_info.endpoint)
+    }
+
+    /// The subscriptions created with a retired key, which should be
+    /// subscribed again with the current key of their tenant. Subscriptions
+    /// with a key not in the ring are not included.
+    pub fn on_retired_keys<'s, I>(&self, subscriptions: I) -> Vec<&'s SubscriptionInfo>
+    where
+        I: IntoIterator<Item = &'s SubscriptionInfo>,
+    {
+        subscriptions
+            .into_iter()
+            .filter(|subscription_info| self.find(subscription_info).is_ok_and(|key| key.retired))
+            .collect()
+    }
+
+    fn find(&self, subscription_info: &SubscriptionInfo) -> Result<&RingKey, VapidSignError> {
+        let public_key = match self.subscriptions.get(&subscription_info.endpoint) {
+            Some(public_key) => public_key.clone(),
+            None => match (&self.default, self.keys.len()) {
+                (Some(public_key), _) => public_key.clone(),
+                (None, 1) => self.keys.keys().next().unwrap().clone(),
+                _ => return Err(VapidSignError::MissingKeyId),
+            },
+        };
+
+        self.keys.get(&public_key).ok_or(VapidSignError::UnknownKey)
+    }
+}
+
+/// Decodes the key id, with or without padding.
+fn public_key(key_id: &str) -> Option<Vec<u8>> {
+    base64::decode_config(key_id.trim_end_matches('='), URL_SAFE_NO_PAD).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message::SubscriptionInfo;
+    use crate::vapid::{VapidKey, VapidKeyRing, VapidSignError, VapidSigner, VapidVerifier};
+    use std::fs::File;
+
+    const ENDPOINT: &str = "https://fcm.googleapis.com/fcm/send/abc";
+
+    fn signer(sub: &str) -> VapidSigner {
+        VapidSigner::new(VapidKey::generate().unwrap(), sub)
+    }
+
+    fn key_id(signer: &VapidSigner) -> String {
+        base64::encode_config(signer.public_key(), base64::URL_SAFE_NO_PAD)
+    }
+
+    fn subscription(ring: &mut VapidKeyRing, endpoint: &str, key_id: &str) -> SubscriptionInfo {
+        ring.set_subscription_key(endpoint, key_id).unwrap();
+        SubscriptionInfo::new(endpoint, "", "")
+    }
+
+    #[test]
+    fn test_signs_with_key_of_subscription() {
+        let mut ring = VapidKeyRing::new();
+        let first = ring.insert("app", signer("mailto:app@example.com"));
+        let second = ring.insert("app", signer("mailto:app@example.com"));
+
+        for key_id in &[&first, &second] {
+            let subscription_info = subscription(&mut ring, ENDPOINT, key_id);
+            let signature = ring.sign(&subscription_info).unwrap();
+            assert_eq!(**key_id, signature.auth_k);
+
+            let authorization = format!("vapid t={}, k={}", signature.auth_t, signature.auth_k);
+            VapidVerifier::verify(ENDPOINT, &authorization, None).unwrap();
+        }
+
+        // Padded key ids work too.
+        let padded = subscription(&mut ring, ENDPOINT, &format!("{}=", first));
+        assert_eq!(first, ring.sign(&padded).unwrap().auth_k);
+
+        assert_eq!(
+            Err(VapidSignError::UnknownKey),
+            ring.set_subscription_key(ENDPOINT, &key_id(&signer("mailto:a@example.com")))
+        );
+        assert_eq!(
+            Err(VapidSignError::UnknownKey),
+            ring.set_subscription_key(ENDPOINT, "not base64!")
+        );
+
+        // A removed key no longer signs for its subscriptions.
+        ring.remove(&first).unwrap();
+        assert_eq!(Some(VapidSignError::UnknownKey), ring.sign(&padded).err());
+    }
+
+    #[test]
+    fn test_default_key() {
+        let key = VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap();
+        let legacy = SubscriptionInfo::new(ENDPOINT, "", "");
+
+        let mut ring = VapidKeyRing::new();
+        let first = ring.insert("app", VapidSigner::new(key, "mailto:app@example.com"));
+
+        // The only key is the default.
+        assert_eq!(first, ring.sign(&legacy).unwrap().auth_k);
+
+        let second = ring.insert("app", signer("mailto:app@example.com"));
+        assert_eq!(Some(VapidSignError::MissingKeyId), ring.sign(&legacy).err());
+
+        ring.set_default(&first).unwrap();
+        assert_eq!(first, ring.sign(&legacy).unwrap().auth_k);
+        assert_eq!(
+            Err(VapidSignError::UnknownKey),
+            ring.set_default("BMo1HqKF6skMZYyk")
+        );
+
+        // A recorded key wins over the default, until it is forgotten.
+        ring.set_subscription_key(ENDPOINT, &second).unwrap();
+        assert_eq!(second, ring.sign(&legacy).unwrap().auth_k);
+        assert!(ring.remove_subscription(ENDPOINT));
+        assert!(!ring.remove_subscription(ENDPOINT));
+        assert_eq!(first, ring.sign(&legacy).unwrap().auth_k);
+
+        // The remaining key is the only one again.
+        ring.remove(&first).unwrap();
+        assert_eq!(second, ring.sign(&legacy).unwrap().auth_k);
+    }
+
+    #[test]
+    fn test_rotation() {
+        let mut ring = VapidKeyRing::new();
+        let old = ring.insert("app", signer("mailto:app@example.com"));
+        let new = ring.insert("app", signer("mailto:app@example.com"));
+
+        assert_eq!(new, key_id(ring.current("app").unwrap()));
+        assert!(ring.retire(&old));
+        assert!(!ring.retire("BMo1HqKF6skMZYyk"));
+        assert!(ring.is_retired(&old));
+        assert!(!ring.is_retired(&new));
+
+        let subscriptions = vec![
+            subscription(&mut ring, "https://example.com/push/a", &old),
+            subscription(&mut ring, "https://example.com/push/b", &new),
+            subscription(&mut ring, "https://example.com/push/c", &old),
+        ];
+        let retired: Vec<_> = ring
+            .on_retired_keys(&subscriptions)
+            .iter()
+            .map(|s| s.endpoint.as_str())
+            .collect();
+
+        assert_eq!(
+            vec!["https://example.com/push/a", "https://example.com/push/c"],
+            retired
+        );
+
+        // Retired keys still sign.
+        assert_eq!(old, ring.sign(&subscriptions[0]).unwrap().auth_k);
+
+        // No active key is left.
+        ring.retire(&new);
+        assert!(ring.current("app").is_none());
+    }
+
+    #[test]
+    fn test_tenants() {
+        let mut ring = VapidKeyRing::new();
+        let news = ring.insert("news", signer("mailto:news@example.com"));
+        let shop = ring.insert("shop", signer("https://shop.example.com/contact"));
+        let shop_new = ring.insert("shop", signer("https://shop.example.com/contact"));
+
+        assert_eq!(news, key_id(ring.current("news").unwrap()));
+        assert_eq!(shop_new, key_id(ring.current("shop").unwrap()));
+        assert!(ring.current("blog").is_none());
+
+        let news_subscription = subscription(&mut ring, "https://example.com/push/news", &news);
+        let shop_subscription = subscription(&mut ring, "https://example.com/push/shop", &shop);
+        assert_eq!(Ok("news"), ring.tenant(&news_subscription));
+        assert_eq!(Ok("shop"), ring.tenant(&shop_subscription));
+
+        ring.retire(&shop_new);
+        assert_eq!(shop, key_id(ring.current("shop").unwrap()));
+    }
+}
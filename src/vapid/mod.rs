@@ -1,10 +1,22 @@
 mod builder;
+mod cache;
+mod claims;
+mod clock;
+mod der;
 mod error;
+mod external;
 mod key;
+mod key_ring;
 mod signer;
+mod verifier;
 
 pub use self::builder::VapidSignatureBuilder;
+pub use self::cache::VapidTokenCache;
+pub use self::claims::VapidClaims;
+pub use self::clock::{Clock, FixedClock, SystemClock};
+pub use self::external::{AsyncEs256Signer, Es256Signer, LocalProcessSigner, SignFuture};
 pub use self::key::VapidKey;
-pub use self::signer::VapidSignature;
-use self::signer::VapidSigner;
-pub use error::{VapidKeyError, VapidSignError};
+pub use self::key_ring::VapidKeyRing;
+pub use self::signer::{VapidScheme, VapidSignature, VapidSigner};
+pub use self::verifier::{VapidVerifier, VerifiedToken};
+pub use error::{VapidKeyError, VapidSignError, VapidVerifyError};
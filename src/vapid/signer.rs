@@ -1,10 +1,16 @@
-use super::VapidKey;
+use super::clock::{unix_time, Clock, SystemClock};
+use super::der;
 use super::VapidSignError;
+use super::{AsyncEs256Signer, Es256Signer, VapidClaims};
+use crate::crypto::{Backend, CryptoBackend};
+use crate::http_ece::ContentEncoding;
 use base64::{self, URL_SAFE_NO_PAD};
-use openssl::hash::MessageDigest;
-use openssl::sign::Signer as SslSigner;
-use serde_json::{Number, Value};
+use serde::Serialize;
+use serde_json::Value;
 use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
 use url::Url;
 
 lazy_static! {
@@ -18,9 +24,18 @@ lazy_static! {
     );
 }
 
+/// How long a token is valid if the claims have no `exp`.
+pub(crate) const DEFAULT_EXPIRY_SECONDS: i64 = 12 * 60 * 60;
+
+/// The `aud` claim for an endpoint, the origin of the push service.
+pub(crate) fn audience(endpoint: &str) -> Result<String, VapidSignError> {
+    let endpoint: Url = Url::parse(endpoint).map_err(|_| VapidSignError::InvalidEndpoint)?;
+    Ok(endpoint.origin().ascii_serialization())
+}
+
 /// A struct representing a VAPID signature. Should be generated using the
 /// [VapidSignatureBuilder](struct.VapidSignatureBuilder.html).
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct VapidSignature {
     /// The signature
     pub auth_t: String,
@@ -28,84 +43,329 @@ pub struct VapidSignature {
     pub auth_k: String,
 }
 
-impl<'a> Into<String> for &'a VapidSignature {
-    fn into(self) -> String {
-        format!("WebPush {}", self.auth_t)
+/// How a VAPID signature is sent to the push service.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VapidScheme {
+    /// `Authorization: vapid t=<jwt>, k=<key>`, as specified in RFC 8292.
+    Vapid,
+    /// `Authorization: WebPush <jwt>` with the key in the `p256ecdsa`
+    /// parameter of the `Crypto-Key` header, from the earlier VAPID drafts.
+    WebPush,
+}
+
+impl VapidScheme {
+    /// The scheme used with the encoding unless set otherwise: `Vapid` for
+    /// aes128gcm, which requires it, and `WebPush` for the older aesgcm.
+    pub fn for_encoding(encoding: &ContentEncoding) -> VapidScheme {
+        match encoding {
+            ContentEncoding::Aes128Gcm => VapidScheme::Vapid,
+            ContentEncoding::AesGcm => VapidScheme::WebPush,
+        }
+    }
+}
+
+impl VapidSignature {
+    /// The headers carrying the signature in the scheme. With `WebPush` the
+    /// key is a `Crypto-Key` parameter, to be joined with the other
+    /// parameters of the header.
+    pub(crate) fn headers(&self, scheme: VapidScheme) -> Vec<(&'static str, String)> {
+        match scheme {
+            VapidScheme::Vapid => vec![(
+                "Authorization",
+                format!("vapid t={}, k={}", self.auth_t, self.auth_k),
+            )],
+            VapidScheme::WebPush => vec![
+                ("Authorization", self.into()),
+                ("Crypto-Key", format!("p256ecdsa={}", self.auth_k)),
+            ],
+        }
     }
 }
 
-pub struct VapidSigner {}
+impl From<&VapidSignature> for String {
+    fn from(signature: &VapidSignature) -> String {
+        format!("WebPush {}", signature.auth_t)
+    }
+}
+
+/// Reusable VAPID signing configuration: the key, the `sub` claim, other
+/// claims and how long the tokens are valid.
+///
+/// Unlike [VapidSignatureBuilder](struct.VapidSignatureBuilder.html) it does
+/// not borrow a subscription, so one signer can be kept in the application
+/// state, cloned cheaply and shared between threads.
+///
+/// ```
+/// # use web_push::*;
+/// # use std::fs::File;
+/// # use std::time::Duration;
+/// let key = VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap();
+///
+/// let mut signer = VapidSigner::new(key, "mailto:admin@example.com");
+/// signer.set_expiry(Duration::from_secs(6 * 60 * 60));
+///
+/// let signature = signer
+///     .sign_for_endpoint("https://fcm.googleapis.com/fcm/send/abc")
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct VapidSigner {
+    key: SigningKey,
+    sub: String,
+    claims: BTreeMap<String, Value>,
+    expiry: Duration,
+    clock: Arc<dyn Clock>,
+}
+
+#[derive(Clone)]
+enum SigningKey {
+    Blocking(Arc<dyn Es256Signer>),
+    Async(Arc<dyn AsyncEs256Signer>),
+}
+
+impl fmt::Debug for VapidSigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("VapidSigner")
+            .field(
+                "public_key",
+                &base64::encode_config(self.public_key(), URL_SAFE_NO_PAD),
+            )
+            .field("sub", &self.sub)
+            .field("claims", &self.claims)
+            .field("expiry", &self.expiry)
+            .finish()
+    }
+}
 
 impl VapidSigner {
-    /// Create a signature with a given key. Sets the default audience from the
-    /// endpoint host and sets the expiry in twelve hours. Values can be
-    /// overwritten by adding the `aud` and `exp` claims.
-    pub fn sign(
-        key: &VapidKey,
-        endpoint: &str,
-        mut claims: BTreeMap<&str, Value>,
-    ) -> Result<VapidSignature, VapidSignError> {
-        if !claims.contains_key("aud") {
-            let endpoint: Url = Url::parse(endpoint).unwrap();
-            let audience = format!("{}://{}", endpoint.scheme(), endpoint.host().unwrap());
-            claims.insert("aud", Value::String(audience));
+    /// A signer for the key, with `sub` being the contact for the push service,
+    /// either a `mailto:` or an `https:` URL. The key can be a
+    /// [VapidKey](struct.VapidKey.html), shared in an `Arc` or not, or any
+    /// other [Es256Signer](trait.Es256Signer.html).
+    pub fn new<K, S>(key: K, sub: S) -> VapidSigner
+    where
+        K: Es256Signer + 'static,
+        S: Into<String>,
+    {
+        Self::with_key(SigningKey::Blocking(Arc::new(key)), sub.into())
+    }
+
+    /// A signer for a key that signs asynchronously. Signatures have to be
+    /// created with the `_async` methods.
+    pub fn new_async<K, S>(key: K, sub: S) -> VapidSigner
+    where
+        K: AsyncEs256Signer + 'static,
+        S: Into<String>,
+    {
+        Self::with_key(SigningKey::Async(Arc::new(key)), sub.into())
+    }
+
+    fn with_key(key: SigningKey, sub: String) -> VapidSigner {
+        VapidSigner {
+            key,
+            sub,
+            claims: BTreeMap::new(),
+            expiry: Duration::from_secs(DEFAULT_EXPIRY_SECONDS as u64),
+            clock: Arc::new(SystemClock::new()),
         }
+    }
 
-        if !claims.contains_key("exp") {
-            let expiry = time::OffsetDateTime::now_utc() + time::Duration::hours(12);
-            let number = Number::from(expiry.unix_timestamp());
-            claims.insert("exp", Value::Number(number));
+    /// Add a claim to every signature. The `aud`, `exp` and `sub` claims are
+    /// always set by the signer.
+    pub fn add_claim<K, V>(&mut self, key: K, val: V)
+    where
+        K: Into<String>,
+        V: Into<Value>,
+    {
+        self.claims.insert(key.into(), val.into());
+    }
+
+    /// How long the signatures are valid from the time of signing, twelve
+    /// hours by default and at most 24 hours.
+    pub fn set_expiry(&mut self, expiry: Duration) {
+        self.expiry = expiry;
+    }
+
+    /// Replaces the system clock used for `exp`.
+    pub fn set_clock<C>(&mut self, clock: C)
+    where
+        C: Clock + 'static,
+    {
+        self.clock = Arc::new(clock);
+    }
+
+    /// The public key of the signing key, the `applicationServerKey` of the
+    /// subscriptions.
+    pub fn public_key(&self) -> &[u8] {
+        match &self.key {
+            SigningKey::Blocking(key) => key.public_key(),
+            SigningKey::Async(key) => key.public_key(),
         }
+    }
+
+    /// The claims a token for the push service origin would have if signed
+    /// now.
+    pub fn claims_for_audience(&self, audience: &str) -> VapidClaims {
+        let now = unix_time(&*self.clock);
 
-        let signing_input = format!(
-            "{}.{}",
-            *JWT_HEADERS,
-            base64::encode_config(&serde_json::to_string(&claims)?, URL_SAFE_NO_PAD)
+        let mut claims = VapidClaims::new(
+            audience,
+            now + self.expiry.as_secs() as i64,
+            self.sub.as_str(),
         );
 
-        let public_key = key.public_key();
-        let auth_k = base64::encode_config(public_key, URL_SAFE_NO_PAD);
+        claims.extra = self
+            .claims
+            .iter()
+            .filter(|(key, _)| !matches!(key.as_str(), "aud" | "exp" | "sub"))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
 
-        let mut signer = SslSigner::new(MessageDigest::sha256(), key.p_key())?;
-        signer.update(signing_input.as_bytes())?;
+        claims
+    }
 
-        let signature = signer.sign_to_vec()?;
+    /// Signs a token for the push service of the endpoint.
+    pub fn sign_for_endpoint(&self, endpoint: &str) -> Result<VapidSignature, VapidSignError> {
+        self.sign_for_audience(&audience(endpoint)?)
+    }
 
-        let r_off: usize = 3;
-        let r_len = signature[r_off] as usize;
-        let s_off: usize = r_off + r_len + 2;
-        let s_len = signature[s_off] as usize;
+    /// Signs a token for the push service origin, such as
+    /// `https://fcm.googleapis.com`.
+    pub fn sign_for_audience(&self, audience: &str) -> Result<VapidSignature, VapidSignError> {
+        self.sign_claims(&self.claims_for_audience(audience))
+    }
 
-        let mut r_val = &signature[(r_off + 1)..(r_off + 1 + r_len)];
-        let mut s_val = &signature[(s_off + 1)..(s_off + 1 + s_len)];
+    /// Signs the claims with the key after checking them with
+    /// [VapidClaims::validate](struct.VapidClaims.html#method.validate).
+    /// Fails with `AsyncSigner` if the key only signs asynchronously.
+    pub fn sign_claims(&self, claims: &VapidClaims) -> Result<VapidSignature, VapidSignError> {
+        claims.validate(unix_time(&*self.clock))?;
 
-        if r_len == 33 && r_val[0] == 0 {
-            r_val = &r_val[1..];
+        match &self.key {
+            SigningKey::Blocking(key) => sign_claims(&**key, claims),
+            SigningKey::Async(_) => Err(VapidSignError::AsyncSigner),
         }
+    }
+
+    /// Like [sign_for_endpoint](#method.sign_for_endpoint), for all keys.
+    pub async fn sign_for_endpoint_async(
+        &self,
+        endpoint: &str,
+    ) -> Result<VapidSignature, VapidSignError> {
+        self.sign_for_audience_async(&audience(endpoint)?).await
+    }
+
+    /// Like [sign_for_audience](#method.sign_for_audience), for all keys.
+    pub async fn sign_for_audience_async(
+        &self,
+        audience: &str,
+    ) -> Result<VapidSignature, VapidSignError> {
+        self.sign_claims_async(&self.claims_for_audience(audience))
+            .await
+    }
+
+    /// Like [sign_claims](#method.sign_claims), for all keys.
+    pub async fn sign_claims_async(
+        &self,
+        claims: &VapidClaims,
+    ) -> Result<VapidSignature, VapidSignError> {
+        claims.validate(unix_time(&*self.clock))?;
 
-        if s_len == 33 && s_val[0] == 0 {
-            s_val = &s_val[1..];
+        match &self.key {
+            SigningKey::Blocking(key) => sign_claims(&**key, claims),
+            SigningKey::Async(key) => {
+                let signing_input = signing_input(claims)?;
+                let signature = key.sign_async(signing_input.as_bytes()).await?;
+
+                finish_signature(key.public_key(), signing_input, signature)
+            }
         }
+    }
+}
 
-        let mut sigval: Vec<u8> = Vec::with_capacity(64);
-        sigval.extend(r_val);
-        sigval.extend(s_val);
+/// The claims for a message to the endpoint at `now`, checked against the
+/// rules of RFC 8292. Sets the default audience from the endpoint origin and
+/// the expiry in twelve hours. Values can be overwritten by adding the `aud`
+/// and `exp` claims.
+pub(crate) fn endpoint_claims(
+    endpoint: &str,
+    claims: BTreeMap<&str, Value>,
+    now: i64,
+) -> Result<VapidClaims, VapidSignError> {
+    let claims =
+        VapidClaims::from_map(claims, || audience(endpoint), now + DEFAULT_EXPIRY_SECONDS)?;
+    claims.validate(now)?;
 
-        trace!("Public key: {}", auth_k);
+    Ok(claims)
+}
 
-        let auth_t = format!(
-            "{}.{}",
-            signing_input,
-            base64::encode_config(&sigval, URL_SAFE_NO_PAD)
-        );
+/// Signs the claims as they are.
+pub(crate) fn sign_claims<K, C>(key: &K, claims: &C) -> Result<VapidSignature, VapidSignError>
+where
+    K: Es256Signer + ?Sized,
+    C: Serialize,
+{
+    let signing_input = signing_input(claims)?;
+    let signature = key.sign(signing_input.as_bytes())?;
+
+    finish_signature(key.public_key(), signing_input, signature)
+}
+
+/// The header and the claims of the token.
+fn signing_input<C>(claims: &C) -> Result<String, VapidSignError>
+where
+    C: Serialize,
+{
+    Ok(format!(
+        "{}.{}",
+        *JWT_HEADERS,
+        base64::encode_config(&serde_json::to_string(claims)?, URL_SAFE_NO_PAD)
+    ))
+}
+
+/// Appends the signature to the signing input after checking it, so that a
+/// misconfigured external signer fails here and not at the push service.
+fn finish_signature(
+    public_key: &[u8],
+    signing_input: String,
+    signature: Vec<u8>,
+) -> Result<VapidSignature, VapidSignError> {
+    let signature = match signature.len() {
+        64 => signature,
+        _ => der::ecdsa_signature(&signature)
+            .map_err(|_| VapidSignError::InvalidSignature)?
+            .to_vec(),
+    };
 
-        Ok(VapidSignature { auth_t, auth_k })
+    if !Backend::es256_verify(public_key, signing_input.as_bytes(), &signature) {
+        return Err(VapidSignError::InvalidSignature);
     }
+
+    let auth_k = base64::encode_config(public_key, URL_SAFE_NO_PAD);
+
+    trace!("Public key: {}", auth_k);
+
+    let auth_t = format!(
+        "{}.{}",
+        signing_input,
+        base64::encode_config(&signature, URL_SAFE_NO_PAD)
+    );
+
+    Ok(VapidSignature { auth_t, auth_k })
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::vapid::VapidSignature;
+    use crate::crypto::{Backend, CryptoBackend};
+    use crate::vapid::signer::{endpoint_claims, sign_claims};
+    use crate::vapid::{
+        FixedClock, VapidClaims, VapidKey, VapidSignError, VapidSignature, VapidSigner,
+        VapidVerifier,
+    };
+    use base64::{self, URL_SAFE_NO_PAD};
+    use std::collections::BTreeMap;
+    use std::fs::File;
+    use std::time::{Duration, UNIX_EPOCH};
 
     #[test]
     fn test_vapid_signature_aesgcm_format() {
@@ -118,4 +378,166 @@ mod tests {
 
         assert_eq!("WebPush foo", &header_value);
     }
+
+    #[test]
+    fn test_vapid_signature_token() {
+        let key = VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap();
+
+        let mut claims = BTreeMap::new();
+        claims.insert("sub", json!("mailto:test@example.com"));
+        claims.insert("exp", json!(1_600_000_000));
+
+        let claims =
+            endpoint_claims("https://example.com/push/foo", claims, 1_600_000_000 - 3600).unwrap();
+        let signature = sign_claims(&key, &claims).unwrap();
+        let parts: Vec<&str> = signature.auth_t.split('.').collect();
+
+        assert_eq!(3, parts.len());
+        assert_eq!("eyJhbGciOiJFUzI1NiIsInR5cCI6IkpXVCJ9", parts[0]);
+        assert_eq!(
+            json!({"aud": "https://example.com", "exp": 1_600_000_000, "sub": "mailto:test@example.com"}),
+            serde_json::from_slice::<serde_json::Value>(
+                &base64::decode_config(parts[1], URL_SAFE_NO_PAD).unwrap()
+            )
+            .unwrap()
+        );
+
+        let raw_signature = base64::decode_config(parts[2], URL_SAFE_NO_PAD).unwrap();
+        let signing_input = format!("{}.{}", parts[0], parts[1]);
+
+        assert_eq!(64, raw_signature.len());
+        assert!(Backend::es256_verify(
+            key.public_key(),
+            signing_input.as_bytes(),
+            &raw_signature
+        ));
+        assert_eq!(
+            "BMo1HqKF6skMZYykrte9duqYwBD08mDQKTunRkJdD3sTJ9E-yyN6sJlPWTpKNhp-y2KeS6oANHF-q3w37bClb7U",
+            signature.auth_k
+        );
+    }
+
+    #[test]
+    fn test_signer_for_endpoint_and_audience() {
+        let key = VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap();
+        let mut signer = VapidSigner::new(key, "mailto:admin@example.com");
+        signer.add_claim("foo", "bar");
+        signer.add_claim("sub", "mailto:overridden@example.com");
+        signer.set_expiry(std::time::Duration::from_secs(3600));
+
+        let endpoint = "https://fcm.googleapis.com/fcm/send/abc";
+
+        for signature in &[
+            signer.sign_for_endpoint(endpoint).unwrap(),
+            signer
+                .clone()
+                .sign_for_audience("https://fcm.googleapis.com")
+                .unwrap(),
+        ] {
+            let authorization = format!("vapid t={}, k={}", signature.auth_t, signature.auth_k);
+            let token = VapidVerifier::verify(endpoint, &authorization, None).unwrap();
+            let now = time::OffsetDateTime::now_utc().unix_timestamp();
+
+            assert_eq!("mailto:admin@example.com", token.sub);
+            assert_eq!(Some(&json!("bar")), token.claims.get("foo"));
+            assert!(token.exp > now + 3590 && token.exp <= now + 3600);
+        }
+
+        assert!(matches!(
+            signer.sign_for_endpoint("not an endpoint"),
+            Err(crate::vapid::VapidSignError::InvalidEndpoint)
+        ));
+    }
+
+    #[test]
+    fn test_signer_is_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<VapidSigner>();
+    }
+
+    #[test]
+    fn test_endpoint_claims_defaults_and_validation() {
+        let now = 1_600_000_000;
+        let mut claims = BTreeMap::new();
+        claims.insert("sub", json!("mailto:test@example.com"));
+
+        assert_eq!(
+            Ok(VapidClaims::new(
+                "http://localhost:8080",
+                now + 12 * 3600,
+                "mailto:test@example.com"
+            )),
+            endpoint_claims("http://localhost:8080/push/foo", claims.clone(), now)
+        );
+
+        let mut with_exp = claims.clone();
+        with_exp.insert("exp", json!(now + 48 * 3600));
+        assert_eq!(
+            Err(VapidSignError::ExpiryTooFar),
+            endpoint_claims("https://example.com/push/foo", with_exp, now)
+        );
+
+        assert_eq!(
+            Err(VapidSignError::MissingSubject),
+            endpoint_claims("https://example.com/push/foo", BTreeMap::new(), now)
+        );
+        assert_eq!(
+            Err(VapidSignError::InvalidEndpoint),
+            endpoint_claims("/push/foo", claims, now)
+        );
+    }
+
+    #[test]
+    fn test_signer_with_fixed_clock() {
+        let key = VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap();
+        let mut signer = VapidSigner::new(key, "mailto:admin@example.com");
+        signer.set_clock(FixedClock::new(
+            UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        ));
+
+        let claims = signer.claims_for_audience("https://fcm.googleapis.com");
+        assert_eq!(1_600_000_000 + 12 * 3600, claims.exp);
+
+        let signature = signer
+            .sign_for_audience("https://fcm.googleapis.com")
+            .unwrap();
+        let payload = signature.auth_t.split('.').nth(1).unwrap();
+        assert_eq!(
+            serde_json::to_value(&claims).unwrap(),
+            serde_json::from_slice::<serde_json::Value>(
+                &base64::decode_config(payload, URL_SAFE_NO_PAD).unwrap()
+            )
+            .unwrap()
+        );
+
+        signer.set_expiry(Duration::from_secs(25 * 3600));
+        assert_eq!(
+            Some(VapidSignError::ExpiryTooFar),
+            signer.sign_for_audience("https://fcm.googleapis.com").err()
+        );
+
+        let mut signer =
+            VapidSigner::new(VapidKey::generate().unwrap(), "https://example.com/contact");
+        signer.set_clock(FixedClock::new(
+            UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        ));
+        let expired = VapidClaims::new(
+            "https://fcm.googleapis.com",
+            1_600_000_000,
+            "https://example.com/contact",
+        );
+        assert_eq!(
+            Some(VapidSignError::Expired),
+            signer.sign_claims(&expired).err()
+        );
+
+        let mut signer = VapidSigner::new(VapidKey::generate().unwrap(), "admin@example.com");
+        signer.set_clock(FixedClock::new(
+            UNIX_EPOCH + Duration::from_secs(1_600_000_000),
+        ));
+        assert_eq!(
+            Some(VapidSignError::InvalidSubject),
+            signer.sign_for_audience("https://fcm.googleapis.com").err()
+        );
+    }
 }
@@ -0,0 +1,379 @@
+use super::claims::{is_valid_subject, MAX_EXPIRY_SECONDS};
+use super::clock::{unix_time, SystemClock};
+use super::VapidVerifyError;
+use crate::crypto::{Backend, CryptoBackend};
+use base64::{self, URL_SAFE_NO_PAD};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use url::Url;
+
+/// The claims of a VAPID token that passed verification.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VerifiedToken {
+    /// The uncompressed public key the token was signed with, from `k` or
+    /// `p256ecdsa`
+    pub public_key: Vec<u8>,
+    /// The origin of the push service
+    pub aud: String,
+    /// The expiry as seconds since the Unix epoch
+    pub exp: i64,
+    /// The contact of the application server, a `mailto:` or `https:` URL
+    pub sub: String,
+    /// All claims in the token, including the ones above
+    pub claims: BTreeMap<String, Value>,
+}
+
+/// Verifies the VAPID `Authorization` headers a push service receives, for
+/// running a stand-in push service or for checking the signatures the crate
+/// creates.
+///
+/// Both the `vapid t=<jwt>, k=<key>` scheme of RFC 8292 and the older
+/// `WebPush <jwt>` scheme with the key in the `p256ecdsa` parameter of the
+/// `Crypto-Key` header are accepted.
+///
+/// ```
+/// # use web_push::*;
+/// # use std::fs::File;
+/// let key = VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap();
+///
+/// let subscription_info = SubscriptionInfo::new(
+///     "https://push.example.com/send/abc",
+///     "BLMbF9ffKBiWQLCKvTHb6LO8Nb6dcUh6TItC455vu2kElga6PQvUmaFyCdykxY2nOSSL3yKgfbmFLRTUaGv4yV8",
+///     "xS03Fi5ErfTNH_l9WHE9Ig",
+/// );
+///
+/// let mut builder = VapidSignatureBuilder::new(&subscription_info);
+/// builder.add_claim("sub", "mailto:admin@example.com");
+/// let signature = builder.sign(&key).unwrap();
+///
+/// let authorization = format!("vapid t={}, k={}", signature.auth_t, signature.auth_k);
+/// let token = VapidVerifier::verify(&subscription_info.endpoint, &authorization, None).unwrap();
+///
+/// assert_eq!("https://push.example.com", token.aud);
+/// assert_eq!("mailto:admin@example.com", token.sub);
+/// assert_eq!(key.public_key(), &token.public_key[..]);
+/// ```
+pub struct VapidVerifier {}
+
+impl VapidVerifier {
+    /// Verifies the token in the `Authorization` header for a message sent
+    /// to `endpoint`. The `Crypto-Key` header is only needed for the
+    /// `WebPush` scheme.
+    pub fn verify(
+        endpoint: &str,
+        authorization: &str,
+        crypto_key: Option<&str>,
+    ) -> Result<VerifiedToken, VapidVerifyError> {
+        let now = unix_time(&SystemClock::new());
+
+        Self::verify_at(endpoint, authorization, crypto_key, now)
+    }
+
+    /// Verifies the token as if the current time was `now` seconds since the
+    /// Unix epoch.
+    pub(crate) fn verify_at(
+        endpoint: &str,
+        authorization: &str,
+        crypto_key: Option<&str>,
+        now: i64,
+    ) -> Result<VerifiedToken, VapidVerifyError> {
+        let (token, key) = parse_authorization(authorization, crypto_key)?;
+
+        let public_key = base64::decode_config(key.trim_end_matches('='), URL_SAFE_NO_PAD)
+            .map_err(|_| VapidVerifyError::InvalidPublicKey)?;
+
+        if public_key.len() != 65 || public_key[0] != 4 {
+            return Err(VapidVerifyError::InvalidPublicKey);
+        }
+
+        let (signing_input, signature) = token
+            .rsplit_once('.')
+            .ok_or(VapidVerifyError::MalformedToken)?;
+        let (header, claims) = match signing_input.split_once('.') {
+            Some((header, claims)) if !claims.contains('.') => (header, claims),
+            _ => return Err(VapidVerifyError::MalformedToken),
+        };
+
+        let header: BTreeMap<String, Value> = decode_json(header)?;
+
+        if header.get("alg").and_then(Value::as_str) != Some("ES256") {
+            return Err(VapidVerifyError::UnsupportedAlgorithm);
+        }
+
+        let signature = base64::decode_config(signature, URL_SAFE_NO_PAD)
+            .map_err(|_| VapidVerifyError::MalformedToken)?;
+
+        if !Backend::es256_verify(&public_key, signing_input.as_bytes(), &signature) {
+            return Err(VapidVerifyError::InvalidSignature);
+        }
+
+        let claims: BTreeMap<String, Value> = decode_json(claims)?;
+
+        let aud = string_claim(&claims, "aud")?;
+        let origin = Url::parse(endpoint)
+            .map_err(|_| VapidVerifyError::InvalidEndpoint)?
+            .origin()
+            .ascii_serialization();
+
+        if aud != origin {
+            return Err(VapidVerifyError::AudienceMismatch);
+        }
+
+        let exp = match claims.get("exp") {
+            Some(exp) => exp.as_i64().ok_or(VapidVerifyError::InvalidClaim("exp"))?,
+            None => return Err(VapidVerifyError::MissingClaim("exp")),
+        };
+
+        if exp <= now {
+            return Err(VapidVerifyError::Expired);
+        }
+
+        if exp > now + MAX_EXPIRY_SECONDS {
+            return Err(VapidVerifyError::ExpiryTooFar);
+        }
+
+        let sub = string_claim(&claims, "sub")?;
+
+        if !is_valid_subject(&sub) {
+            return Err(VapidVerifyError::InvalidSubject);
+        }
+
+        Ok(VerifiedToken {
+            public_key,
+            aud,
+            exp,
+            sub,
+            claims,
+        })
+    }
+}
+
+/// The token and the public key from the headers.
+fn parse_authorization<'a>(
+    authorization: &'a str,
+    crypto_key: Option<&'a str>,
+) -> Result<(&'a str, &'a str), VapidVerifyError> {
+    let authorization = authorization.trim();
+    let (scheme, credentials) = authorization
+        .split_once(' ')
+        .ok_or(VapidVerifyError::MalformedHeader)?;
+
+    if scheme.eq_ignore_ascii_case("vapid") {
+        let token = param(credentials, "t").ok_or(VapidVerifyError::MalformedHeader)?;
+        let key = param(credentials, "k").ok_or(VapidVerifyError::MissingPublicKey)?;
+
+        Ok((token, key))
+    } else if scheme.eq_ignore_ascii_case("WebPush") {
+        let key = crypto_key
+            .and_then(|crypto_key| param(crypto_key, "p256ecdsa"))
+            .ok_or(VapidVerifyError::MissingPublicKey)?;
+
+        Ok((credentials.trim(), key))
+    } else {
+        Err(VapidVerifyError::MalformedHeader)
+    }
+}
+
+/// Finds a parameter in a header value in the form of `t=..., k=...` or
+/// `dh=...; p256ecdsa=...`.
+fn param<'h>(value: &'h str, name: &str) -> Option<&'h str> {
+    value
+        .split(&[';', ','][..])
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| key.trim() == name)
+        .map(|(_, value)| value.trim().trim_matches('"'))
+}
+
+fn decode_json(segment: &str) -> Result<BTreeMap<String, Value>, VapidVerifyError> {
+    let json = base64::decode_config(segment, URL_SAFE_NO_PAD)
+        .map_err(|_| VapidVerifyError::MalformedToken)?;
+
+    serde_json::from_slice(&json).map_err(|_| VapidVerifyError::MalformedToken)
+}
+
+fn string_claim(
+    claims: &BTreeMap<String, Value>,
+    name: &'static str,
+) -> Result<String, VapidVerifyError> {
+    match claims.get(name) {
+        Some(Value::String(value)) => Ok(value.clone()),
+        Some(_) => Err(VapidVerifyError::InvalidClaim(name)),
+        None => Err(VapidVerifyError::MissingClaim(name)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::vapid::signer;
+    use crate::vapid::{VapidKey, VapidVerifier, VapidVerifyError};
+    use base64::{self, URL_SAFE_NO_PAD};
+    use serde_json::Value;
+    use std::collections::BTreeMap;
+    use std::fs::File;
+
+    const ENDPOINT: &str = "https://push.example.com/send/abc";
+    const NOW: i64 = 1_600_000_000;
+
+    fn key() -> VapidKey {
+        VapidKey::from_pem(File::open("resources/vapid_test_key.pem").unwrap()).unwrap()
+    }
+
+    /// Signs the claims without checking them, with `aud` set to the origin
+    /// of the endpoint unless given.
+    fn sign(claims: Value) -> (String, String) {
+        let mut claims: BTreeMap<String, Value> = serde_json::from_value(claims).unwrap();
+        claims
+            .entry(String::from("aud"))
+            .or_insert_with(|| json!("https://push.example.com"));
+        let signature = signer::sign_claims(&key(), &claims).unwrap();
+
+        (signature.auth_t, signature.auth_k)
+    }
+
+    fn verify(claims: Value) -> Result<super::VerifiedToken, VapidVerifyError> {
+        let (t, k) = sign(claims);
+        VapidVerifier::verify_at(ENDPOINT, &format!("vapid t={}, k={}", t, k), None, NOW)
+    }
+
+    #[test]
+    fn test_verify_vapid_scheme() {
+        let token = verify(json!({
+            "sub": "mailto:admin@example.com",
+            "exp": NOW + 3600,
+            "foo": "bar"
+        }))
+        .unwrap();
+
+        assert_eq!(key().public_key(), &token.public_key[..]);
+        assert_eq!("https://push.example.com", token.aud);
+        assert_eq!(NOW + 3600, token.exp);
+        assert_eq!("mailto:admin@example.com", token.sub);
+        assert_eq!(Some(&json!("bar")), token.claims.get("foo"));
+    }
+
+    #[test]
+    fn test_verify_webpush_scheme() {
+        let (t, k) = sign(json!({"sub": "https://example.com/contact", "exp": NOW + 60}));
+        let authorization = format!("WebPush {}", t);
+        let crypto_key = format!("dh=BBXpqeMbtt1iwSoYzs7uRL; p256ecdsa={}", k);
+
+        let token =
+            VapidVerifier::verify_at(ENDPOINT, &authorization, Some(&crypto_key), NOW).unwrap();
+        assert_eq!("https://example.com/contact", token.sub);
+
+        assert_eq!(
+            Err(VapidVerifyError::MissingPublicKey),
+            VapidVerifier::verify_at(
+                ENDPOINT,
+                &authorization,
+                Some("dh=BBXpqeMbtt1iwSoYzs7uRL"),
+                NOW
+            )
+        );
+        assert_eq!(
+            Err(VapidVerifyError::MissingPublicKey),
+            VapidVerifier::verify_at(ENDPOINT, &authorization, None, NOW)
+        );
+    }
+
+    #[test]
+    fn test_verify_signed_with_current_time() {
+        let subscription_info =
+            crate::SubscriptionInfo::new("http://localhost:8080/push/abc", "", "");
+        let mut builder = crate::VapidSignatureBuilder::new(&subscription_info);
+        builder.add_claim("sub", "mailto:admin@example.com");
+        let signature = builder.sign(&key()).unwrap();
+
+        let token = VapidVerifier::verify(
+            "http://localhost:8080/push/abc",
+            &format!("vapid t={}, k={}", signature.auth_t, signature.auth_k),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!("http://localhost:8080", token.aud);
+    }
+
+    #[test]
+    fn test_verify_rejects_claims() {
+        let exp = NOW + 3600;
+
+        assert_eq!(
+            Err(VapidVerifyError::AudienceMismatch),
+            verify(
+                json!({"sub": "mailto:a@example.com", "exp": exp, "aud": "https://example.com"})
+            )
+        );
+        assert_eq!(
+            Err(VapidVerifyError::Expired),
+            verify(json!({"sub": "mailto:a@example.com", "exp": NOW}))
+        );
+        assert_eq!(
+            Err(VapidVerifyError::ExpiryTooFar),
+            verify(json!({"sub": "mailto:a@example.com", "exp": NOW + 24 * 3600 + 1}))
+        );
+        assert!(verify(json!({"sub": "mailto:a@example.com", "exp": NOW + 24 * 3600})).is_ok());
+        assert_eq!(
+            Err(VapidVerifyError::InvalidClaim("exp")),
+            verify(json!({"sub": "mailto:a@example.com", "exp": "tomorrow"}))
+        );
+        assert_eq!(
+            Err(VapidVerifyError::MissingClaim("sub")),
+            verify(json!({"exp": exp}))
+        );
+        assert_eq!(
+            Err(VapidVerifyError::InvalidSubject),
+            verify(json!({"sub": "http://example.com", "exp": exp}))
+        );
+        assert_eq!(
+            Err(VapidVerifyError::InvalidSubject),
+            verify(json!({"sub": "admin@example.com", "exp": exp}))
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_tokens() {
+        let (t, k) = sign(json!({"sub": "mailto:a@example.com", "exp": NOW + 60}));
+        let verify =
+            |authorization: String| VapidVerifier::verify_at(ENDPOINT, &authorization, None, NOW);
+
+        let other_key = VapidKey::generate().unwrap().application_server_key();
+        assert_eq!(
+            Err(VapidVerifyError::InvalidSignature),
+            verify(format!("vapid t={}, k={}", t, other_key))
+        );
+
+        let mut parts: Vec<String> = t.split('.').map(String::from).collect();
+        parts[1] = base64::encode_config(
+            r#"{"aud":"https://push.example.com","exp":1600000060,"sub":"mailto:b@example.com"}"#,
+            URL_SAFE_NO_PAD,
+        );
+        assert_eq!(
+            Err(VapidVerifyError::InvalidSignature),
+            verify(format!("vapid t={}, k={}", parts.join("."), k))
+        );
+
+        parts[0] = base64::encode_config(r#"{"alg":"none","typ":"JWT"}"#, URL_SAFE_NO_PAD);
+        assert_eq!(
+            Err(VapidVerifyError::UnsupportedAlgorithm),
+            verify(format!("vapid t={}, k={}", parts.join("."), k))
+        );
+
+        assert_eq!(
+            Err(VapidVerifyError::MalformedToken),
+            verify(format!("vapid t={}.foo, k={}", t, k))
+        );
+        assert_eq!(
+            Err(VapidVerifyError::InvalidPublicKey),
+            verify(format!("vapid t={}, k={}", t, &k[..20]))
+        );
+        assert_eq!(
+            Err(VapidVerifyError::MissingPublicKey),
+            verify(format!("vapid t={}", t))
+        );
+        assert_eq!(
+            Err(VapidVerifyError::MalformedHeader),
+            verify(format!("Bearer {}", t))
+        );
+    }
+}